@@ -0,0 +1,159 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use fs2::FileExt;
+
+use crate::error::{Error, Result};
+
+/// symlinks followed before giving up, like `MAXSYMLINKS` on Linux
+const MAX_SYMLINKS: usize = 40;
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// replace the contents of `path` without leaving a partially written file behind
+///
+/// The contents are written to a temporary file next to the target, synced and renamed over it.
+/// If `path` is a symlink, the file it points to is replaced and the link is kept.
+/// The permissions of an existing file are kept.
+pub fn write(path: &dyn AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+    let target = resolve_symlinks(path.as_ref())?;
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = match target.file_name() {
+        Some(file_name) => file_name.to_string_lossy(),
+        None => {
+            return Err(Error::io(&target)(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a file",
+            )))
+        }
+    };
+    let temp = dir.join(format!(
+        ".{file_name}.{}-{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = (|| -> io::Result<()> {
+        let mut f = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        f.write_all(contents.as_ref())?;
+        match fs::metadata(&target) {
+            Ok(metadata) => f.set_permissions(metadata.permissions())?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        f.sync_all()?;
+        fs::rename(&temp, &target)?;
+        // persist the rename, not every platform can sync a directory
+        if let Ok(dir) = File::open(&dir) {
+            let _ = dir.sync_all();
+        }
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result.map_err(Error::io(&target))
+}
+
+/// take an exclusive advisory lock for updating `path`, held until the returned file is dropped
+///
+/// The lock is taken on `path` with a `.lock` suffix, because writing replaces the file itself.
+/// Blocks while another process holds the lock.
+pub fn lock(path: &dyn AsRef<Path>) -> Result<File> {
+    let mut lock_path = path.as_ref().as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
+    let f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(Error::io(&lock_path))?;
+    f.lock_exclusive().map_err(Error::io(&lock_path))?;
+    Ok(f)
+}
+
+/// the file `path` points to, which may not exist yet
+fn resolve_symlinks(path: &Path) -> Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_SYMLINKS {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let link = fs::read_link(&path).map_err(Error::io(&path))?;
+                path = match path.parent() {
+                    Some(parent) => parent.join(link),
+                    None => link,
+                };
+            }
+            _ => return Ok(path),
+        }
+    }
+    Err(Error::io(&path)(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "too many levels of symbolic links",
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn shrink() {
+        let dir = TempDir::new("atomic-shrink");
+        let p = dir.join("gtk.css");
+        super::write(&p, "@define-color window_bg_color #242424;\n").unwrap();
+        super::write(&p, "/* */\n").unwrap();
+        assert_eq!(fs::read_to_string(&p).unwrap(), "/* */\n");
+        // no temporary files are left behind
+        assert_eq!(fs::read_dir(&*dir).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_and_permissions() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = TempDir::new("atomic-symlink");
+        fs::create_dir(dir.join("dotfiles")).unwrap();
+        let target = dir.join("dotfiles").join("gtk.css");
+        fs::write(&target, "old contents which are longer\n").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o640)).unwrap();
+        let link = dir.join("gtk.css");
+        symlink("dotfiles/gtk.css", &link).unwrap();
+
+        super::write(&link, "new\n").unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new\n");
+        assert_eq!(
+            fs::metadata(&target).unwrap().permissions().mode() & 0o777,
+            0o640
+        );
+
+        // a dangling link creates its target
+        let dangling = dir.join("cosmic.css");
+        symlink("dotfiles/cosmic.css", &dangling).unwrap();
+        super::write(&dangling, "created\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("dotfiles").join("cosmic.css")).unwrap(),
+            "created\n"
+        );
+    }
+}
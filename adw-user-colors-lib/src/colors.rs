@@ -1,20 +1,72 @@
 // SPDX-License-Identifier: MPL-2.0-only
 
 use std::fmt::Write as _;
-use std::{
-    fs::File,
-    io::Write,
-    path::{Path, PathBuf},
-}; // import without risk of name clashing
+use std::path::{Path, PathBuf}; // import without risk of name clashing
 
 use serde::{Deserialize, Serialize};
 
-use crate::{NAME, THEME_DIR};
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    store::{ThemeStore, XdgThemeStore},
+    theme::{slugify, Theme},
+};
+
+/// Whether a theme is meant for light or dark mode
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Variant {
+    Light,
+    Dark,
+}
+
+impl Variant {
+    pub fn is_dark(self) -> bool {
+        self == Variant::Dark
+    }
+}
+
+/// the color keys of [`ColorOverrides`], in the order of the fields
+pub const KEYS: [&str; 31] = [
+    "accent_bg_color",
+    "accent_fg_color",
+    "accent_color",
+    "destructive_bg_color",
+    "destructive_fg_color",
+    "destructive_color",
+    "success_color",
+    "success_bg_color",
+    "success_fg_color",
+    "warning_color",
+    "warning_bg_color",
+    "warning_fg_color",
+    "error_color",
+    "error_bg_color",
+    "error_fg_color",
+    "window_bg_color",
+    "window_fg_color",
+    "view_bg_color",
+    "view_fg_color",
+    "headerbar_bg_color",
+    "headerbar_fg_color",
+    "headerbar_border_color",
+    "headerbar_backdrop_color",
+    "headerbar_shade_color",
+    "card_bg_color",
+    "card_fg_color",
+    "card_shade_color",
+    "popover_bg_color",
+    "popover_fg_color",
+    "scrollbar_outline_color",
+    "shade_color",
+];
 
 #[derive(Debug, Default, Deserialize, Serialize, Clone, Hash, PartialEq, Eq)]
+#[serde(default)]
 pub struct ColorOverrides {
     /// name
     pub name: String,
+    /// explicit variant, overrides the variant derived from the background colors
+    pub variant: Option<Variant>,
     pub accent_bg_color: Option<String>,
     pub accent_fg_color: Option<String>,
     pub accent_color: Option<String>,
@@ -64,45 +116,46 @@ pub struct ColorOverrides {
     // Miscellaneous
     pub scrollbar_outline_color: Option<String>,
     pub shade_color: Option<String>,
+
+    /// css appended after the colors, for tweaks no color covers, see [`check_custom_css`]
+    pub custom_css: Option<String>,
 }
 
 impl ColorOverrides {
-    pub fn save(&self) -> anyhow::Result<()> {
-        let ron_path: PathBuf = [NAME, THEME_DIR].iter().collect();
-        let ron_dirs = xdg::BaseDirectories::with_prefix(ron_path)?;
-        let ron_name = format!("{}.ron", &self.name);
-
-        if let Ok(p) = ron_dirs.place_data_file(ron_name) {
-            let mut f = File::create(p)?;
-            f.write_all(ron::ser::to_string(self)?.as_bytes())?;
-        } else {
-            anyhow::bail!("Failed to write RON theme.");
-        }
-        Ok(())
+    /// save the overrides as a single variant theme, replacing the theme with the id of the name
+    pub fn save(&self, config: &Config) -> Result<()> {
+        self.save_in(&mut config.theme_store()?)
     }
 
-    pub fn init() -> anyhow::Result<PathBuf> {
-        let ron_path: PathBuf = [NAME, THEME_DIR].iter().collect();
-        let base_dirs = xdg::BaseDirectories::new()?;
-        Ok(base_dirs.create_data_directory(ron_path)?)
+    fn save_in(&self, store: &mut dyn ThemeStore) -> Result<()> {
+        let mut theme = Theme::from(self.clone());
+        theme.id = slugify(&self.name);
+        store.put(&mut theme)
     }
 
-    pub fn load_from_name(name: &str) -> anyhow::Result<Self> {
-        let ron_path: PathBuf = [NAME, THEME_DIR].iter().collect();
-        let ron_dirs = xdg::BaseDirectories::with_prefix(ron_path)?;
+    pub fn init() -> Result<PathBuf> {
+        XdgThemeStore::with_extra_dirs(Vec::new())?.init()
+    }
 
-        let ron_name = format!("{}.ron", name);
-        if let Some(p) = ron_dirs.find_data_file(ron_name) {
-            let f = File::open(p)?;
-            Ok(ron::de::from_reader(f)?)
-        } else {
-            anyhow::bail!("Failed to write RON theme.");
+    /// the first variant of the theme with the id `name`, in the theme dirs of `config`
+    pub fn load_from_name(config: &Config, name: &str) -> Result<Self> {
+        let theme = config.theme_store()?.get(name)?;
+        let overrides = theme
+            .slots()
+            .next()
+            .and_then(|slot| theme.get(slot).cloned());
+        match overrides {
+            Some(overrides) => Ok(overrides),
+            None => Err(Error::EmptyTheme {
+                name: name.to_string(),
+            }),
         }
     }
 
-    pub fn load(p: &dyn AsRef<Path>) -> anyhow::Result<Self> {
-        let f = File::open(p)?;
-        Ok(ron::de::from_reader(f)?)
+    pub fn load(p: &dyn AsRef<Path>) -> Result<Self> {
+        let p = p.as_ref();
+        let s = std::fs::read_to_string(p).map_err(Error::io(p))?;
+        ron::from_str(&s).map_err(|err| Error::parse(Some(p), err))
     }
 
     pub fn light_default() -> Self {
@@ -113,65 +166,113 @@ impl ColorOverrides {
         ron::de::from_bytes(include_bytes!("dark_default.ron")).unwrap()
     }
 
+    /// classify the theme as light or dark
+    ///
+    /// Uses the explicit `variant` if there is one, otherwise the relative luminance of
+    /// `window_bg_color`, falling back to `view_bg_color`.
+    /// Returns `None` if neither is set to a valid color.
+    pub fn variant(&self) -> Option<Variant> {
+        if let Some(variant) = self.variant {
+            return Some(variant);
+        }
+        let bg = self
+            .window_bg_color
+            .as_ref()
+            .and_then(|c| csscolorparser::parse(c).ok())
+            .or_else(|| {
+                self.view_bg_color
+                    .as_ref()
+                    .and_then(|c| csscolorparser::parse(c).ok())
+            })?;
+        // black and white text have the same contrast against this luminance
+        if relative_luminance(&bg) < 0.179 {
+            Some(Variant::Dark)
+        } else {
+            Some(Variant::Light)
+        }
+    }
+
     /// ensures that all colors in the palette meet high-contrast constraints
     pub fn to_high_contrast(self) -> Self {
         // TODO
         self
     }
 
-    pub fn set_key(&mut self, key: &str, value: Option<String>) -> anyhow::Result<()> {
-        match key {
-            "accent_bg_color" => self.accent_bg_color = value,
-            "accent_fg_color" => self.accent_fg_color = value,
-            "accent_color" => self.accent_color = value,
+    /// set the color `key` to `value`, which must be a color, see [`is_valid_color`]
+    pub fn set_key(&mut self, key: &str, value: Option<String>) -> Result<()> {
+        if let Some(value) = &value {
+            if !is_valid_color(value) {
+                return Err(Error::InvalidColor {
+                    key: key.to_string(),
+                    value: value.clone(),
+                });
+            }
+        }
+        match self.key_mut(key) {
+            Some(field) => {
+                *field = value;
+                Ok(())
+            }
+            None => Err(Error::UnknownKey {
+                key: key.to_string(),
+            }),
+        }
+    }
+
+    /// the field of the color `key`
+    pub(crate) fn key_mut(&mut self, key: &str) -> Option<&mut Option<String>> {
+        let field = match key {
+            "accent_bg_color" => &mut self.accent_bg_color,
+            "accent_fg_color" => &mut self.accent_fg_color,
+            "accent_color" => &mut self.accent_color,
 
             // destructive-action buttons
-            "destructive_bg_color" => self.destructive_bg_color = value,
-            "destructive_fg_color" => self.destructive_fg_color = value,
-            "destructive_color" => self.destructive_color = value,
+            "destructive_bg_color" => &mut self.destructive_bg_color,
+            "destructive_fg_color" => &mut self.destructive_fg_color,
+            "destructive_color" => &mut self.destructive_color,
 
-            "success_color" => self.success_color = value,
-            "success_bg_color" => self.success_color = value,
-            "success_fg_color" => self.success_color = value,
+            "success_color" => &mut self.success_color,
+            "success_bg_color" => &mut self.success_bg_color,
+            "success_fg_color" => &mut self.success_fg_color,
 
-            "warning_color" => self.warning_color = value,
-            "warning_bg_color" => self.warning_color = value,
-            "warning_fg_color" => self.warning_color = value,
+            "warning_color" => &mut self.warning_color,
+            "warning_bg_color" => &mut self.warning_bg_color,
+            "warning_fg_color" => &mut self.warning_fg_color,
 
-            "error_color" => self.error_color = value,
-            "error_bg_color" => self.error_color = value,
-            "error_fg_color" => self.error_color = value,
+            "error_color" => &mut self.error_color,
+            "error_bg_color" => &mut self.error_bg_color,
+            "error_fg_color" => &mut self.error_fg_color,
 
             // Content areas, e.g. text views
-            "view_bg_color" => self.view_bg_color = value,
-            "view_fg_color" => self.view_fg_color = value,
+            "view_bg_color" => &mut self.view_bg_color,
+            "view_fg_color" => &mut self.view_fg_color,
 
             // Main window background
-            "window_bg_color" => self.window_bg_color = value,
-            "window_fg_color" => self.window_fg_color = value,
+            "window_bg_color" => &mut self.window_bg_color,
+            "window_fg_color" => &mut self.window_fg_color,
 
             // Header bar, search bar, tab bar
-            "headerbar_bg_color" => self.headerbar_bg_color = value,
-            "headerbar_fg_color" => self.headerbar_fg_color = value,
-            "headerbar_border_color" => self.headerbar_border_color = value,
-            "headerbar_backdrop_color" => self.headerbar_backdrop_color = value,
-            "headerbar_shade_color" => self.headerbar_shade_color = value,
+            "headerbar_bg_color" => &mut self.headerbar_bg_color,
+            "headerbar_fg_color" => &mut self.headerbar_fg_color,
+            "headerbar_border_color" => &mut self.headerbar_border_color,
+            "headerbar_backdrop_color" => &mut self.headerbar_backdrop_color,
+            "headerbar_shade_color" => &mut self.headerbar_shade_color,
 
             // Cards, boxed lists
-            "card_bg_color" => self.card_bg_color = value,
-            "card_fg_color" => self.card_fg_color = value,
-            "card_shade_color" => self.card_shade_color = value,
+            "card_bg_color" => &mut self.card_bg_color,
+            "card_fg_color" => &mut self.card_fg_color,
+            "card_shade_color" => &mut self.card_shade_color,
 
             // Popovers
-            "popover_bg_color" => self.popover_bg_color = value,
-            "popover_fg_color" => self.popover_fg_color = value,
+            "popover_bg_color" => &mut self.popover_bg_color,
+            "popover_fg_color" => &mut self.popover_fg_color,
 
             // Miscellaneous
-            "scrollbar_outline_color" => self.scrollbar_outline_color = value,
-            "shade_color" => self.shade_color = value,
-            _ => anyhow::bail!("Invalid key"),
-        }
-        Ok(())
+            "scrollbar_outline_color" => &mut self.scrollbar_outline_color,
+            "shade_color" => &mut self.shade_color,
+            _ => return None,
+        };
+        Some(field)
     }
 
     pub fn get_key(&self, key: &str) -> Option<String> {
@@ -186,16 +287,16 @@ impl ColorOverrides {
             "destructive_color" => self.destructive_color.clone(),
 
             "success_color" => self.success_color.clone(),
-            "success_bg_color" => self.success_color.clone(),
-            "success_fg_color" => self.success_color.clone(),
+            "success_bg_color" => self.success_bg_color.clone(),
+            "success_fg_color" => self.success_fg_color.clone(),
 
             "warning_color" => self.warning_color.clone(),
-            "warning_bg_color" => self.warning_color.clone(),
-            "warning_fg_color" => self.warning_color.clone(),
+            "warning_bg_color" => self.warning_bg_color.clone(),
+            "warning_fg_color" => self.warning_fg_color.clone(),
 
             "error_color" => self.error_color.clone(),
-            "error_bg_color" => self.error_color.clone(),
-            "error_fg_color" => self.error_color.clone(),
+            "error_bg_color" => self.error_bg_color.clone(),
+            "error_fg_color" => self.error_fg_color.clone(),
 
             // Content areas.clone(), e.g. text views
             "view_bg_color" => self.view_bg_color.clone(),
@@ -228,239 +329,452 @@ impl ColorOverrides {
         }
     }
 
+    /// the colors as GTK css, leaving out values which aren't colors
     pub fn as_gtk_css(&self) -> String {
-        let mut user_color_css = String::new();
-        if let Some(accent_bg_color) = self.accent_bg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color accent_bg_color {};",
-                &accent_bg_color
-            );
-        }
-        if let Some(accent_fg_color) = self.accent_fg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color accent_fg_color {};",
-                &accent_fg_color
-            );
-        }
-        if let Some(accent_color) = self.accent_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color accent_color {};",
-                &accent_color
+        let (css, rejected) = self.gtk_css_checked();
+        for r in rejected {
+            log::warn!(
+                "Left out {} of {}, {} is not a color",
+                r.key,
+                self.name,
+                r.value
             );
         }
+        css
+    }
 
-        if let Some(destructive_bg_color) = self.destructive_bg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color destructive_bg_color {};",
-                &destructive_bg_color
-            );
-        }
-        if let Some(destructive_fg_color) = self.destructive_fg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color destructive_fg_color {};",
-                &destructive_fg_color
-            );
-        }
-        if let Some(destructive_color) = self.destructive_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color destructive_color {};",
-                &destructive_color
-            );
-        }
+    /// the colors as `@define-color` rules followed by the custom css, and the values left out
+    ///
+    /// Themes are shared, so colors are checked with [`is_valid_color`] and the custom css with
+    /// [`check_custom_css`] before they are written into stylesheets which apply to every
+    /// application.
+    pub fn gtk_css_checked(&self) -> (String, Vec<RejectedValue>) {
+        let mut css = String::new();
+        let mut rejected = Vec::new();
+        for key in KEYS {
+            match self.get_key(key) {
+                Some(value) if is_valid_color(&value) => {
+                    let _ = writeln!(css, "@define-color {key} {value};");
+                }
+                Some(value) => rejected.push(RejectedValue { key, value }),
+                None => {}
+            }
+        }
+        match &self.custom_css {
+            Some(custom) if check_custom_css(custom).is_ok() => {
+                let _ = writeln!(css, "\n{}", custom.trim_end());
+            }
+            Some(custom) => rejected.push(RejectedValue {
+                key: "custom_css",
+                value: custom.clone(),
+            }),
+            None => {}
+        }
+        (css, rejected)
+    }
 
-        if let Some(success_color) = self.success_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color success_color {};",
-                &success_color
-            );
-        }
-        if let Some(success_bg_color) = self.success_bg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color success_bg_color {};",
-                &success_bg_color
-            );
-        }
-        if let Some(success_fg_color) = self.success_fg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color success_fg_color {};",
-                &success_fg_color
-            );
-        }
-        if let Some(warning_color) = self.warning_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color warning_color {};",
-                &warning_color
-            );
-        }
-        if let Some(warning_bg_color) = self.warning_bg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color warning_bg_color {};",
-                &warning_bg_color
-            );
-        }
-        if let Some(warning_fg_color) = self.warning_fg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color warning_fg_color {};",
-                &warning_fg_color
-            );
-        }
-        if let Some(error_color) = self.error_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color error_color {};",
-                &error_color
-            );
-        }
-        if let Some(error_bg_color) = self.error_bg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color error_bg_color {};",
-                &error_bg_color
-            );
-        }
-        if let Some(error_fg_color) = self.error_fg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color error_fg_color {};",
-                &error_fg_color
-            );
-        }
+    /// replace the colors and custom css with those in `css`, the inverse of [`Self::as_gtk_css`]
+    ///
+    /// Lines defining one of the [`KEYS`] set that color, colors without a line are unset.
+    /// The remaining lines, e.g. other `@define-color` rules pasted from another stylesheet,
+    /// become the custom css. Nothing is changed if a color or the custom css is invalid.
+    pub fn set_gtk_css(&mut self, css: &str) -> Result<()> {
+        check_custom_css(css)?;
+        let mut parsed = ColorOverrides {
+            name: self.name.clone(),
+            variant: self.variant,
+            ..Default::default()
+        };
+        let mut custom = Vec::new();
+        for line in css.lines() {
+            let rule = line
+                .trim()
+                .strip_prefix("@define-color")
+                .filter(|rule| rule.starts_with(char::is_whitespace))
+                .map(|rule| {
+                    let rule = rule.trim().trim_end_matches(';');
+                    rule.split_once(char::is_whitespace).unwrap_or((rule, ""))
+                })
+                .filter(|(key, _)| KEYS.contains(key));
+            match rule {
+                Some((key, value)) => parsed.set_key(key, Some(value.trim().to_string()))?,
+                None => custom.push(line),
+            }
+        }
+        let custom = custom.join("\n");
+        let custom = custom.trim();
+        if !custom.is_empty() {
+            check_custom_css(custom)?;
+            parsed.custom_css = Some(custom.to_string());
+        }
+        *self = parsed;
+        Ok(())
+    }
+}
 
-        if let Some(window_bg_color) = self.window_bg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color window_bg_color {};",
-                &window_bg_color
-            );
-        }
-        if let Some(window_fg_color) = self.window_fg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color window_fg_color {};",
-                &window_fg_color
-            );
-        }
+/// A value which was left out of the css, see [`ColorOverrides::gtk_css_checked`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedValue {
+    pub key: &'static str,
+    pub value: String,
+}
 
-        if let Some(view_bg_color) = self.view_bg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color view_bg_color {};",
-                &view_bg_color
-            );
-        }
-        if let Some(view_fg_color) = self.view_fg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color view_fg_color {};",
-                &view_fg_color
-            );
-        }
-        if let Some(shade_color) = self.shade_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color shade_color {};",
-                &shade_color
-            );
-        }
+/// GTK color functions, with the number of colors they take and whether a number follows
+const COLOR_FUNCTIONS: [(&str, usize, bool); 5] = [
+    ("alpha", 1, true),
+    ("mix", 2, true),
+    ("shade", 1, true),
+    ("lighter", 1, false),
+    ("darker", 1, false),
+];
+
+/// whether `value` is a color GTK understands
+///
+/// That is a CSS color, a reference like `@window_bg_color` or a GTK color expression like
+/// `alpha(@window_fg_color, 0.5)`. Anything else could end the rule it is written into.
+pub fn is_valid_color(value: &str) -> bool {
+    // can't be part of a color, but can start another rule or a comment
+    if value.contains([';', '{', '}', '"', '\'', '\\']) || value.contains("/*") {
+        return false;
+    }
+    let value = value.trim();
+    if let Some(name) = value.strip_prefix('@') {
+        return !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    }
+    let call = value
+        .strip_suffix(')')
+        .and_then(|v| v.split_once('('))
+        .and_then(|(function, args)| {
+            let (_, colors, number) = COLOR_FUNCTIONS
+                .iter()
+                .find(|(f, ..)| *f == function.trim())?;
+            // e.g. rgb(), which is left to the css parser
+            let args = match split_args(args) {
+                Some(args) => args,
+                None => return Some(false),
+            };
+            Some(
+                args.len() == colors + usize::from(*number)
+                    && args[..*colors].iter().all(|c| is_valid_color(c))
+                    && args[*colors..]
+                        .iter()
+                        .all(|n| n.trim().parse::<f64>().is_ok()),
+            )
+        });
+    match call {
+        Some(valid) => valid,
+        None => is_css_color(value),
+    }
+}
 
-        if let Some(headerbar_bg_color) = self.headerbar_bg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color headerbar_bg_color {};",
-                &headerbar_bg_color
-            );
-        }
-        if let Some(headerbar_fg_color) = self.headerbar_fg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color headerbar_fg_color {};",
-                &headerbar_fg_color
-            );
-        }
-        if let Some(headerbar_border_color) = self.headerbar_border_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color headerbar_border_color {};",
-                &headerbar_border_color
-            );
-        }
-        if let Some(headerbar_backdrop_color) = self.headerbar_backdrop_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color headerbar_backdrop_color {};",
-                &headerbar_backdrop_color
-            );
-        }
-        if let Some(headerbar_shade_color) = self.headerbar_shade_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color headerbar_shade_color {};",
-                &headerbar_shade_color
-            );
-        }
+/// whether `value` is a hex, `rgb[a]()`, `hsl[a]()` or named color, the CSS colors GTK parses
+///
+/// The css parser also takes colors GTK rejects, e.g. hex without `#` or `hwb()`.
+fn is_css_color(value: &str) -> bool {
+    let gtk_syntax = match value.strip_prefix('#') {
+        Some(hex) => {
+            matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => match value.strip_suffix(')').and_then(|v| v.split_once('(')) {
+            // only plain numbers, GTK doesn't know units like `turn`
+            Some((function, args)) => {
+                ["rgb", "rgba", "hsl", "hsla"].contains(&function.trim())
+                    && args
+                        .chars()
+                        .all(|c| c.is_ascii_digit() || " .,%+-".contains(c))
+            }
+            None => value.chars().all(|c| c.is_ascii_alphabetic()),
+        },
+    };
+    gtk_syntax && csscolorparser::parse(value).is_ok()
+}
 
-        if let Some(card_bg_color) = self.card_bg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color card_bg_color {};",
-                &card_bg_color
-            );
-        }
-        if let Some(card_fg_color) = self.card_fg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color card_fg_color {};",
-                &card_fg_color
-            );
-        }
-        if let Some(card_shade_color) = self.card_shade_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color card_shade_color {};",
-                &card_shade_color
-            );
+/// check that `css` stays within the subset allowed in [`ColorOverrides::custom_css`]
+///
+/// Blocks, brackets, strings and comments must be closed, so the css can't swallow the rules
+/// after it. `@import` and `url()` are rejected, a theme must not load other files. Escapes are
+/// rejected as well, they could spell either of them.
+pub fn check_custom_css(css: &str) -> Result<()> {
+    let mut line = 1;
+    let err = |line, reason| Err(Error::InvalidCustomCss { line, reason });
+    // the brackets which are open and the line they were opened on
+    let mut open: Vec<(char, usize)> = Vec::new();
+    let mut chars = css.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            '/' if chars.next_if(|(_, c)| *c == '*').is_some() => {
+                let start = line;
+                let mut closed = false;
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\n' => line += 1,
+                        '*' if chars.next_if(|(_, c)| *c == '/').is_some() => {
+                            closed = true;
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                if !closed {
+                    return err(start, "unterminated comment");
+                }
+            }
+            '"' | '\'' => {
+                let mut closed = false;
+                for (_, s) in chars.by_ref() {
+                    match s {
+                        '\\' => return err(line, "escapes are not allowed"),
+                        '\n' => break,
+                        s if s == c => {
+                            closed = true;
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                if !closed {
+                    return err(line, "unterminated string");
+                }
+            }
+            '\\' => return err(line, "escapes are not allowed"),
+            '{' | '(' | '[' => open.push((c, line)),
+            '}' | ')' | ']' => {
+                let expected = match c {
+                    '}' => '{',
+                    ')' => '(',
+                    _ => '[',
+                };
+                if open.pop().map(|(o, _)| o) != Some(expected) {
+                    return err(line, "unexpected closing bracket");
+                }
+            }
+            '@' if starts_with_ignore_case(&css[i..], "@import") => {
+                return err(line, "@import is not allowed");
+            }
+            'u' | 'U' if starts_with_ignore_case(&css[i..], "url(") => {
+                return err(line, "url() is not allowed");
+            }
+            _ => {}
         }
+    }
+    match open.pop() {
+        Some((_, line)) => err(line, "unclosed bracket"),
+        None => Ok(()),
+    }
+}
 
-        if let Some(popover_bg_color) = self.popover_bg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color popover_bg_color {};",
-                &popover_bg_color
-            );
-        }
-        if let Some(popover_fg_color) = self.popover_fg_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color popover_fg_color {};",
-                &popover_fg_color
-            );
-        }
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+    s.get(..prefix.len())
+        .map(|s| s.eq_ignore_ascii_case(prefix))
+        .unwrap_or_default()
+}
 
-        if let Some(scrollbar_outline_color) = self.scrollbar_outline_color.as_ref() {
-            let _ = writeln!(
-                user_color_css,
-                "@define-color scrollbar_outline_color {};",
-                &scrollbar_outline_color
-            );
+/// split the arguments of a function call at the top level commas, `None` if the brackets don't match
+fn split_args(args: &str) -> Option<Vec<&str>> {
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut split = Vec::new();
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                split.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
         }
-        user_color_css
     }
+    split.push(&args[start..]);
+    (depth == 0).then_some(split)
+}
+
+/// WCAG relative luminance of a color, ignoring alpha
+pub fn relative_luminance(c: &csscolorparser::Color) -> f64 {
+    let linear = |c: f64| {
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(c.r) + 0.7152 * linear(c.g) + 0.0722 * linear(c.b)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{check_custom_css, is_valid_color, ColorOverrides, RejectedValue, Variant, KEYS};
+    use crate::{
+        error::Error,
+        store::{MemoryThemeStore, ThemeStore},
+    };
+
+    #[test]
+    fn save_overwrites() {
+        let mut store = MemoryThemeStore::default();
+        let mut overrides = ColorOverrides {
+            name: "Night Owl".into(),
+            ..ColorOverrides::dark_default()
+        };
+        overrides.save_in(&mut store).unwrap();
+        overrides.accent_color = Some("#e01b24".into());
+        overrides.save_in(&mut store).unwrap();
+        assert_eq!(store.list().unwrap(), ["night-owl"]);
+        assert_eq!(
+            store.get("night-owl").unwrap().dark.unwrap().accent_color,
+            overrides.accent_color
+        );
+    }
+
+    #[test]
+    fn css_injection() {
+        for valid in [
+            "#3584e4",
+            "rgba(0, 0, 0, 0.8)",
+            "@window_bg_color",
+            "alpha(@window_fg_color, 0.5)",
+            "mix(@accent_bg_color, rgb(255, 255, 255), .3)",
+            "shade(lighter(#ffffff), 0.9)",
+            "hsl(120, 50%, 50%)",
+            "red",
+        ] {
+            assert!(is_valid_color(valid), "{valid}");
+        }
+        for invalid in [
+            "red; } * { font-size: 0 } {",
+            "red /* */",
+            "@window_bg_color; }",
+            "alpha(@window_fg_color)",
+            "mix(@a, @b, c)",
+            "alpha(red, 0.5))",
+            "url(\"evil.css\")",
+            "",
+            "ff0000",
+            "hwb(0, 0%, 0%)",
+            "hsl(0.5turn, 50%, 50%)",
+            "alpha(ff0000, 0.5)",
+        ] {
+            assert!(!is_valid_color(invalid), "{invalid}");
+        }
+
+        let mut overrides = ColorOverrides {
+            window_bg_color: Some("#242424".into()),
+            ..Default::default()
+        };
+        overrides.accent_color = Some("red; } * { font-size: 0 } {".into());
+        let (css, rejected) = overrides.gtk_css_checked();
+        assert_eq!(css, "@define-color window_bg_color #242424;\n");
+        assert_eq!(
+            rejected,
+            [RejectedValue {
+                key: "accent_color",
+                value: "red; } * { font-size: 0 } {".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn custom_css() {
+        let selection = "textview text selection {\n  background-color: alpha(@accent_bg_color, 0.5);\n}\n/* rounder cards */\n.card { border-radius: 16px; }\n";
+        check_custom_css(selection).unwrap();
+        check_custom_css("label { font-family: \"Fira Sans\", 'x}'; }").unwrap();
+        for (invalid, at) in [
+            ("label { color: red;", 1),
+            ("label {}\n}\n* { color: red }", 2),
+            ("label { color: red )", 1),
+            ("\n/* label {}", 2),
+            ("label { font-family: \"x; }", 1),
+            ("@IMPORT \"other.css\";", 1),
+            ("\nwindow { background-image: url(\"/tmp/x.png\"); }", 2),
+            ("window { background-image: \\75 rl(\"/tmp/x.png\"); }", 1),
+            ("@\\69mport \"other.css\";", 1),
+            ("label { font-family: \"\\\"x\"; }", 1),
+        ] {
+            match check_custom_css(invalid) {
+                Err(Error::InvalidCustomCss { line, .. }) => assert_eq!(line, at, "{invalid}"),
+                res => panic!("{invalid}: {res:?}"),
+            }
+        }
+
+        let mut overrides = ColorOverrides {
+            window_bg_color: Some("#242424".into()),
+            custom_css: Some(selection.into()),
+            ..Default::default()
+        };
+        let (css, rejected) = overrides.gtk_css_checked();
+        assert!(rejected.is_empty());
+        assert!(css.starts_with("@define-color window_bg_color #242424;\n\ntextview"));
+        assert!(css.ends_with("border-radius: 16px; }\n"));
+
+        overrides.custom_css = Some("} * { color: red }".into());
+        let (css, rejected) = overrides.gtk_css_checked();
+        assert_eq!(css, "@define-color window_bg_color #242424;\n");
+        assert_eq!(rejected[0].key, "custom_css");
+    }
+
+    #[test]
+    fn gtk_css_round_trip() {
+        let mut overrides = ColorOverrides::dark_default();
+        overrides.name = "Dark".into();
+        overrides.accent_color = Some("alpha(@accent_bg_color, 0.8)".into());
+        overrides.custom_css = Some(".card {\n  border-radius: 16px;\n}".into());
+        let mut parsed = ColorOverrides {
+            name: "Dark".into(),
+            ..Default::default()
+        };
+        parsed.set_gtk_css(&overrides.as_gtk_css()).unwrap();
+        assert_eq!(parsed, overrides);
+
+        // pasted from another stylesheet, unknown colors are kept as custom css
+        let pasted = "/* colors */\n  @define-color window_bg_color  #fafafa ;\n@define-color dialog_bg_color #ffffff;\n@define-color-x accent_color red;\n";
+        parsed.set_gtk_css(pasted).unwrap();
+        assert_eq!(parsed.window_bg_color.as_deref(), Some("#fafafa"));
+        assert_eq!(parsed.accent_color, None);
+        assert_eq!(
+            parsed.custom_css.as_deref(),
+            Some("/* colors */\n@define-color dialog_bg_color #ffffff;\n@define-color-x accent_color red;")
+        );
+
+        for invalid in [
+            "@define-color window_bg_color red; }",
+            "@define-color window_bg_color;",
+            "@import \"other.css\";",
+        ] {
+            assert!(parsed.set_gtk_css(invalid).is_err(), "{invalid}");
+        }
+        assert_eq!(parsed.window_bg_color.as_deref(), Some("#fafafa"));
+    }
+
+    #[test]
+    fn keys() {
+        for key in KEYS {
+            let mut overrides = ColorOverrides::default();
+            overrides.set_key(key, Some("#123456".into())).unwrap();
+            // each key is stored in its own field
+            for other in KEYS {
+                let expected = (other == key).then(|| "#123456".to_string());
+                assert_eq!(overrides.get_key(other), expected, "{key} / {other}");
+            }
+        }
+
+        let mut overrides = ColorOverrides::default();
+        assert!(matches!(
+            overrides.set_key("accent_colour", None),
+            Err(Error::UnknownKey { .. })
+        ));
+        assert!(matches!(
+            overrides.set_key("accent_color", Some("#12345".into())),
+            Err(Error::InvalidColor { .. })
+        ));
+        assert!(overrides
+            .set_key("accent_color", Some("rgba(0, 0, 0, 0.8)".into()))
+            .is_ok());
+    }
+
     #[test]
     fn light_default() {
         super::ColorOverrides::light_default();
@@ -470,4 +784,23 @@ mod tests {
     fn dark_default() {
         super::ColorOverrides::dark_default();
     }
+
+    #[test]
+    fn variant() {
+        assert_eq!(
+            ColorOverrides::light_default().variant(),
+            Some(Variant::Light)
+        );
+        assert_eq!(
+            ColorOverrides::dark_default().variant(),
+            Some(Variant::Dark)
+        );
+        assert_eq!(ColorOverrides::default().variant(), None);
+
+        let explicit = ColorOverrides {
+            variant: Some(Variant::Light),
+            ..ColorOverrides::dark_default()
+        };
+        assert_eq!(explicit.variant(), Some(Variant::Light));
+    }
 }
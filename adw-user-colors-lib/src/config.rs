@@ -1,209 +1,520 @@
 // SPDX-License-Identifier: MPL-2.0-only
 
-use crate::{colors::ColorOverrides, NAME, THEME_DIR};
-use anyhow::{bail, Result};
-use serde::{Deserialize, Serialize};
-use std::{
-    fs::{File, OpenOptions},
-    io::{prelude::*, BufReader},
-    path::PathBuf,
+use crate::{
+    atomic,
+    colors::{ColorOverrides, RejectedValue},
+    error::{Error, Result},
+    gtk_css::{self, BlockPosition},
+    store::{ThemeStore, XdgThemeStore},
+    theme::{slugify, VariantSlot},
+    NAME,
 };
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Version of the config layout written by this library
+///
+/// 1. `DarkLight` / `Static` enum, see [`LegacyConfig`]
+/// 2. struct with a mode and options shared by all modes
+pub const CONFIG_VERSION: u32 = 2;
 
 /// Cosmic Theme config
+///
+/// Missing fields take their default value and unknown fields are ignored,
+/// so configs written by other versions of the library can still be loaded.
 #[derive(Debug, Deserialize, Serialize, Clone)]
-#[serde(deny_unknown_fields)]
-pub enum Config {
+#[serde(default)]
+pub struct Config {
+    /// layout version, see [`CONFIG_VERSION`]
+    pub version: u32,
+    /// how the active theme is selected
+    pub mode: Mode,
+    /// whether gtk.css imports the overrides, so they apply to all GTK4 applications
+    pub apply_all: bool,
+    /// extra dirs to search for themes, e.g. a shared checkout of team themes
+    ///
+    /// A leading `~` is replaced with the home dir.
+    pub theme_paths: Vec<PathBuf>,
+    /// where the import of the overrides is placed in gtk.css
+    pub gtk_css_position: BlockPosition,
+    /// whether the custom css of the active theme is applied along with its colors when
+    /// `apply_all` is set
+    ///
+    /// Can be turned off if a theme's css breaks some applications.
+    pub apply_custom_css: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            mode: Default::default(),
+            apply_all: Default::default(),
+            theme_paths: Default::default(),
+            gtk_css_position: Default::default(),
+            apply_custom_css: true,
+        }
+    }
+}
+
+/// How the active theme is selected
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub enum Mode {
     DarkLight {
         /// whether high contrast mode is activated
+        #[serde(default)]
         is_high_contrast: bool,
         /// active
+        #[serde(default)]
         is_dark: bool,
-        /// Selected light theme name
+        /// Selected light theme id
+        #[serde(default)]
         light: String,
-        /// Selected dark theme name
+        /// Selected dark theme id
+        #[serde(default)]
         dark: String,
+        /// Selected high contrast light theme id, `light` is made high contrast if empty
+        #[serde(default)]
+        high_contrast_light: String,
+        /// Selected high contrast dark theme id, `dark` is made high contrast if empty
+        #[serde(default)]
+        high_contrast_dark: String,
     },
     Static {
+        /// Selected theme id
+        #[serde(default)]
         name: String,
-        apply_all: bool,
     },
 }
 
-impl Default for Config {
+impl Default for Mode {
     fn default() -> Self {
         // TODO load gsettings to determine if dark light or high contrast?
         Self::DarkLight {
             is_dark: true,
             light: Default::default(),
             dark: Default::default(),
+            high_contrast_light: Default::default(),
+            high_contrast_dark: Default::default(),
             is_high_contrast: Default::default(),
         }
     }
 }
 
+/// Config layout version 1, used before `apply_all` was shared by both modes
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+enum LegacyConfig {
+    DarkLight {
+        is_high_contrast: bool,
+        is_dark: bool,
+        light: String,
+        dark: String,
+        #[serde(default)]
+        high_contrast_light: String,
+        #[serde(default)]
+        high_contrast_dark: String,
+    },
+    Static {
+        name: String,
+        apply_all: bool,
+    },
+}
+
+impl From<LegacyConfig> for Config {
+    /// migrate version 1 to version 2
+    fn from(legacy: LegacyConfig) -> Self {
+        let version = 2;
+        match legacy {
+            LegacyConfig::DarkLight {
+                is_high_contrast,
+                is_dark,
+                light,
+                dark,
+                high_contrast_light,
+                high_contrast_dark,
+            } => Config {
+                version,
+                mode: Mode::DarkLight {
+                    is_high_contrast,
+                    is_dark,
+                    light,
+                    dark,
+                    high_contrast_light,
+                    high_contrast_dark,
+                },
+                apply_all: false,
+                theme_paths: Vec::new(),
+                gtk_css_position: BlockPosition::Bottom,
+                apply_custom_css: true,
+            },
+            LegacyConfig::Static { name, apply_all } => Config {
+                version,
+                mode: Mode::Static { name },
+                apply_all,
+                theme_paths: Vec::new(),
+                gtk_css_position: BlockPosition::Bottom,
+                apply_custom_css: true,
+            },
+        }
+    }
+}
+
 pub const CONFIG_NAME: &str = "config";
 
+/// times [`Config::update`] reads the config again after it was changed by another process
+const UPDATE_ATTEMPTS: usize = 5;
+
+/// gtk.css from before the editor first changed it, in the config dir
+///
+/// Stored as a RON `Option<String>`, `None` if there was no gtk.css.
+const GTK_CSS_BACKUP: &str = "gtk.css.bak.ron";
+
+/// The files written when a config is applied, to undo applying it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppliedState {
+    /// each file with its contents, `None` if it didn't exist
+    files: Vec<(PathBuf, Option<String>)>,
+}
+
+impl AppliedState {
+    /// read the current contents of `paths`
+    fn capture(paths: Vec<PathBuf>) -> Result<Self> {
+        let files = paths
+            .into_iter()
+            .map(|p| read_if_exists(&p).map(|contents| (p, contents)))
+            .collect::<Result<_>>()?;
+        Ok(Self { files })
+    }
+
+    /// write the files back as they were captured
+    pub fn restore(&self) -> Result<()> {
+        for (p, contents) in &self.files {
+            match contents {
+                Some(contents) => atomic::write(p, contents)?,
+                None => remove_if_exists(p)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the contents of `p`, `None` if it doesn't exist
+fn read_if_exists(p: &Path) -> Result<Option<String>> {
+    match std::fs::read_to_string(p) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::io(p)(err)),
+    }
+}
+
+/// remove `p`, which may not exist
+fn remove_if_exists(p: &Path) -> Result<()> {
+    match std::fs::remove_file(p) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(Error::io(p)(err)),
+        _ => Ok(()),
+    }
+}
+
 impl Config {
     /// create a new cosmic theme config
     pub fn new_dark_light(is_dark: bool, high_contrast: bool, light: String, dark: String) -> Self {
-        Self::DarkLight {
-            is_dark,
-            light,
-            dark,
-            is_high_contrast: high_contrast,
+        Self {
+            mode: Mode::DarkLight {
+                is_dark,
+                light,
+                dark,
+                high_contrast_light: Default::default(),
+                high_contrast_dark: Default::default(),
+                is_high_contrast: high_contrast,
+            },
+            ..Default::default()
         }
     }
 
     /// create a new cosmic theme config
     pub fn new_static(name: String, apply_all: bool) -> Self {
-        Self::Static { name, apply_all }
+        Self {
+            mode: Mode::Static { name },
+            apply_all,
+            ..Default::default()
+        }
     }
 
-    /// save the cosmic theme config
+    /// parse a config, migrating configs saved in older layouts
+    ///
+    /// Returns the version the config was migrated from, if it was.
+    pub fn from_ron(s: &str) -> Result<(Self, Option<u32>)> {
+        let (mut config, version) = match ron::from_str::<Self>(s) {
+            Ok(c) => {
+                let version = c.version;
+                (c, version)
+            }
+            Err(err) => match ron::from_str::<LegacyConfig>(s) {
+                Ok(legacy) => (legacy.into(), 1),
+                Err(_) => return Err(Error::parse(None, err)),
+            },
+        };
+
+        // future migrations from version 2 go here, each bumping `config.version`
+        config.version = config.version.max(CONFIG_VERSION);
+
+        Ok((config, (version < CONFIG_VERSION).then_some(version)))
+    }
+
+    /// save the cosmic theme config, replacing changes made by other processes
+    ///
+    /// Prefer [`Config::update`] to change only some fields.
     pub fn save(&self) -> Result<()> {
-        let xdg_dirs = xdg::BaseDirectories::with_prefix(NAME)?;
-        if let Ok(path) = xdg_dirs.place_config_file(PathBuf::from(format!("{CONFIG_NAME}.ron"))) {
-            let mut f = File::create(path)?;
-            let ron = ron::ser::to_string_pretty(&self, Default::default())?;
-            f.write_all(ron.as_bytes())?;
-            Ok(())
-        } else {
-            bail!("failed to save theme config")
+        self.save_at(&Self::path()?)
+    }
+
+    fn save_at(&self, path: &Path) -> Result<()> {
+        let ron = ron::ser::to_string_pretty(&self, Default::default())?;
+        let _lock = atomic::lock(&path)?;
+        if let Some(current) = read_if_exists(path)? {
+            Self::check_version(path, &current)?;
+        }
+        atomic::write(&path, ron)
+    }
+
+    /// refuse to replace a config written by a newer version, its unknown fields would be lost
+    fn check_version(path: &Path, ron: &str) -> Result<()> {
+        match Self::from_ron(ron) {
+            Ok((config, _)) if config.version > CONFIG_VERSION => Err(Error::NewerVersion {
+                path: path.to_path_buf(),
+                version: config.version,
+            }),
+            // a broken config is replaced
+            _ => Ok(()),
+        }
+    }
+
+    /// change the saved config with `f` and save it, keeping changes made by other processes
+    ///
+    /// If the config changed between reading and saving it, `f` is applied again to the new
+    /// version. Returns the saved config.
+    pub fn update(f: impl FnMut(&mut Self)) -> Result<Self> {
+        Self::update_at(&Self::path()?, f)
+    }
+
+    fn update_at(path: &Path, mut f: impl FnMut(&mut Self)) -> Result<Self> {
+        let mut expected = read_if_exists(path)?;
+        // taken after `f` ran once, so other processes aren't blocked while it runs
+        let mut lock = None;
+        for _ in 0..UPDATE_ATTEMPTS {
+            let mut config = match &expected {
+                Some(ron) => Self::from_ron(ron).map_err(|err| err.in_file(path))?.0,
+                None => Self::default(),
+            };
+            f(&mut config);
+            let ron = ron::ser::to_string_pretty(&config, Default::default())?;
+
+            if lock.is_none() {
+                lock = Some(atomic::lock(&path)?);
+            }
+            let current = read_if_exists(path)?;
+            if let Some(current) = &current {
+                Self::check_version(path, current)?;
+            }
+            if current == expected {
+                if current.as_deref() != Some(&ron) {
+                    atomic::write(&path, ron)?;
+                }
+                return Ok(config);
+            }
+            // changed by another process since it was read
+            expected = current;
         }
+        Err(Error::ConcurrentUpdate)
     }
 
-    pub fn init() -> anyhow::Result<PathBuf> {
+    /// path of the config file, its dir is created if necessary
+    fn path() -> Result<PathBuf> {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix(NAME)?;
+        let name = format!("{CONFIG_NAME}.ron");
+        xdg_dirs
+            .place_config_file(&name)
+            .map_err(Error::io(xdg_dirs.get_config_home().join(&name)))
+    }
+
+    pub fn init() -> Result<PathBuf> {
         let base_dirs = xdg::BaseDirectories::new()?;
-        Ok(base_dirs.create_config_directory(NAME)?)
+        base_dirs
+            .create_config_directory(NAME)
+            .map_err(Error::io(base_dirs.get_config_home().join(NAME)))
     }
 
     /// load the cosmic theme config
     pub fn load() -> Result<Self> {
-        let xdg_dirs = xdg::BaseDirectories::with_prefix(NAME)?;
-        let path = xdg_dirs.get_config_home();
-        std::fs::create_dir_all(&path)?;
-        let path = xdg_dirs.find_config_file(PathBuf::from(format!("{CONFIG_NAME}.ron")));
-        if path.is_none() {
-            let s = Self::default();
-            s.save()?;
-        }
-        if let Some(path) = xdg_dirs.find_config_file(PathBuf::from(format!("{CONFIG_NAME}.ron"))) {
-            let mut f = File::open(&path)?;
-            let mut s = String::new();
-            f.read_to_string(&mut s)?;
-            Ok(ron::from_str(s.as_str())?)
-        } else {
-            anyhow::bail!("Failed to load config")
+        let path = Self::path()?;
+        let s = match read_if_exists(&path)? {
+            Some(s) => s,
+            // another process may be creating it at the same time
+            None => return Self::update(|_| {}),
+        };
+        let (config, migrated_from) = Self::from_ron(&s).map_err(|err| err.in_file(&path))?;
+        if let Some(version) = migrated_from {
+            // keep the old file around in case the migration lost something
+            let backup = path.with_file_name(format!("{CONFIG_NAME}.v{version}.ron.bak"));
+            if !backup.exists() {
+                std::fs::copy(&path, &backup).map_err(Error::io(&backup))?;
+            }
+            config.save()?;
         }
+        Ok(config)
     }
 
     #[cfg(feature = "gtk4")]
     /// applies the active config to to xdg-config-dir/gtk-4.0/cosmic.css
-    pub fn apply_gtk4(&self) -> anyhow::Result<()> {
-        let active = match self.active_name() {
-            Some(n) => n,
-            _ => anyhow::bail!("No configured active overrides"),
-        };
-        let css_path: PathBuf = [NAME, THEME_DIR].iter().collect();
-        let css_dirs = xdg::BaseDirectories::with_prefix(css_path)?;
-        let active_theme_path = match css_dirs.find_data_file(format!("{active}.ron")) {
-            Some(p) => p,
-            _ => anyhow::bail!("Could not find theme"),
-        };
-        let active_theme_file = File::open(active_theme_path)?;
-        let reader = BufReader::new(active_theme_file);
-
-        let colors = ron::de::from_reader::<_, ColorOverrides>(reader)?;
-        let colors = match &self {
-            Config::DarkLight {
-                is_high_contrast: high_contrast,
-                ..
-            } if *high_contrast => colors.to_high_contrast(),
-            _ => colors,
-        };
+    ///
+    /// Returns the values of the theme which were left out because they aren't colors.
+    pub fn apply_gtk4(&self) -> Result<Vec<RejectedValue>> {
+        let mut colors = self.get_active()?;
+        // the toggle only applies when the colors are imported for all applications
+        if self.apply_all && !self.apply_custom_css {
+            colors.custom_css = None;
+        }
 
-        let user_color_css = &mut colors.as_gtk_css();
+        let (user_color_css, rejected) = colors.gtk_css_checked();
         let xdg_dirs = xdg::BaseDirectories::with_prefix("gtk-4.0")?;
-        let path = xdg_dirs.place_config_file(PathBuf::from("cosmic.css"))?;
+        let path = xdg_dirs
+            .place_config_file("cosmic.css")
+            .map_err(Error::io(xdg_dirs.get_config_home().join("cosmic.css")))?;
         // write out css
-        std::fs::write(&path, &user_color_css)?;
-
-        let import = "@import url(\"cosmic.css\");";
-
-        match self {
-            Config::Static { apply_all, .. } if *apply_all => {
-                // import if necessary
-                if let Some(f) = xdg_dirs.find_config_file(PathBuf::from("gtk.css")) {
-                    // let gtk_css_import = &format!("\n{import}\n");
-                    let import_missing = {
-                        let file = File::open(&f)?;
-                        let reader = BufReader::new(file);
-                        !reader.lines().any(|l| {
-                            l.as_ref()
-                                .ok()
-                                .and_then(|l| if l.contains(import) { Some(()) } else { None })
-                                .is_some()
-                        })
-                    };
-                    if import_missing {
-                        let mut file = OpenOptions::new().write(true).append(true).open(f)?;
-
-                        writeln!(file, "\n{import}")?;
-                    }
-                } else if let Ok(f) = xdg_dirs.place_config_file(PathBuf::from("gtk.css")) {
-                    let mut file = OpenOptions::new().write(true).append(true).open(f)?;
+        atomic::write(&path, user_color_css)?;
 
-                    writeln!(file, "\n{import}")?;
-                }
+        let (gtk_css, backup) = Self::gtk_css_paths()?;
+        if self.apply_all {
+            Self::set_gtk_css_block(
+                &gtk_css,
+                &backup,
+                Some(&[gtk_css::IMPORT]),
+                self.gtk_css_position,
+            )?;
+        } else {
+            Config::unimport()?;
+        }
+        Ok(rejected)
+    }
 
-                Ok(())
-            }
-            _ => Config::unimport(),
+    /// remove the block importing the overrides from gtk.css
+    pub fn unimport() -> Result<()> {
+        let (gtk_css, backup) = Self::gtk_css_paths()?;
+        if gtk_css.exists() {
+            Self::set_gtk_css_block(&gtk_css, &backup, None, BlockPosition::default())?;
         }
+        Ok(())
     }
 
-    pub fn unimport() -> anyhow::Result<()> {
-        let import = "@import url(\"cosmic.css\");";
+    /// remove all customizations, e.g. after a theme made applications unreadable
+    ///
+    /// gtk.css is restored from the backup taken before it was first changed, so later changes to it are lost.
+    /// cosmic.css is deleted and the config is reset.
+    pub fn reset_all() -> Result<()> {
+        let (gtk_css, backup) = Self::gtk_css_paths()?;
+        Self::restore_gtk_css(&gtk_css, &backup)?;
         let xdg_dirs = xdg::BaseDirectories::with_prefix("gtk-4.0")?;
+        remove_if_exists(&xdg_dirs.get_config_home().join("cosmic.css"))?;
+        Self::default().save()
+    }
 
-        if let Some(f) = xdg_dirs.find_config_file(PathBuf::from("gtk.css")) {
-            // let gtk_css_import = &format!("\n{import}\n");
-            let mut changed = false;
-            let new_contents: Vec<String> = {
-                let file = File::open(&f)?;
-                let reader = BufReader::new(file);
-                reader
-                    .lines()
-                    .filter_map(|l| {
-                        l.ok().and_then(|mut l| {
-                            if l == import {
-                                changed = true;
-                                None
-                            } else if let Some(start_index) = l.find(import) {
-                                changed = true;
-                                l.replace_range(start_index..start_index + import.len(), "");
-                                Some(l)
-                            } else {
-                                Some(l)
-                            }
-                        })
-                    })
-                    .collect()
-            };
-            if changed {
-                let mut file = OpenOptions::new().write(true).open(f)?;
-                let new_contents = new_contents.join("\n");
-                write!(file, "{new_contents}")?;
-                return Ok(());
+    /// the applied config and colors, to restore them if the user doesn't confirm new colors
+    pub fn applied_state() -> Result<AppliedState> {
+        let config = xdg::BaseDirectories::with_prefix(NAME)?
+            .get_config_home()
+            .join(format!("{CONFIG_NAME}.ron"));
+        let gtk_dir = xdg::BaseDirectories::with_prefix("gtk-4.0")?.get_config_home();
+        AppliedState::capture(vec![
+            config,
+            gtk_dir.join("cosmic.css"),
+            gtk_dir.join("gtk.css"),
+        ])
+    }
+
+    /// the user's gtk.css and the backup of it
+    fn gtk_css_paths() -> Result<(PathBuf, PathBuf)> {
+        let gtk_css = xdg::BaseDirectories::with_prefix("gtk-4.0")?
+            .get_config_home()
+            .join("gtk.css");
+        let backup = xdg::BaseDirectories::with_prefix(NAME)?
+            .get_config_home()
+            .join(GTK_CSS_BACKUP);
+        Ok((gtk_css, backup))
+    }
+
+    /// update the managed block of the gtk.css at `f`, see [`gtk_css::set_block`]
+    ///
+    /// The file is copied to `backup` before it is changed for the first time.
+    fn set_gtk_css_block(
+        f: &Path,
+        backup: &Path,
+        content: Option<&[&str]>,
+        position: BlockPosition,
+    ) -> Result<()> {
+        let original = read_if_exists(f)?;
+        let css = original.as_deref().unwrap_or_default();
+        if let Some(new_css) = gtk_css::set_block(css, content, position)? {
+            if !backup.exists() {
+                if let Some(dir) = backup.parent() {
+                    std::fs::create_dir_all(dir).map_err(Error::io(dir))?;
+                }
+                atomic::write(&backup, ron::to_string(&original)?)?;
             }
+            atomic::write(&f, new_css)?;
         }
         Ok(())
     }
 
-    /// get the name of the active theme
-    pub fn active_name(&self) -> Option<String> {
-        match self {
-            Config::DarkLight {
+    /// restore the gtk.css at `f` from `backup`, or remove the managed block if there is no backup
+    fn restore_gtk_css(f: &Path, backup: &Path) -> Result<()> {
+        match read_if_exists(backup)? {
+            Some(ron) => {
+                let original: Option<String> =
+                    ron::from_str(&ron).map_err(|err| Error::parse(Some(backup), err))?;
+                match original {
+                    Some(css) => atomic::write(&f, css)?,
+                    None => remove_if_exists(f)?,
+                }
+                std::fs::remove_file(backup).map_err(Error::io(backup))?;
+            }
+            None => {
+                let css = match read_if_exists(f)? {
+                    Some(css) => css,
+                    None => return Ok(()),
+                };
+                if let Some(new_css) = gtk_css::remove_block(&css)? {
+                    atomic::write(&f, new_css)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// the extra theme dirs, with `~` expanded
+    pub fn theme_dirs(&self) -> Vec<PathBuf> {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        self.theme_paths
+            .iter()
+            .map(|p| match (p.strip_prefix("~"), &home) {
+                (Ok(rest), Some(home)) => home.join(rest),
+                _ => p.clone(),
+            })
+            .collect()
+    }
+
+    /// the store of the themes in the user, extra and system dirs
+    pub fn theme_store(&self) -> Result<XdgThemeStore> {
+        XdgThemeStore::new(self.theme_dirs())
+    }
+
+    /// get the id of the active theme
+    pub fn active_id(&self) -> Option<String> {
+        if let Some(id) = self.high_contrast_id() {
+            return Some(id);
+        }
+        match &self.mode {
+            Mode::DarkLight {
                 light,
                 dark,
                 is_dark,
@@ -211,84 +522,391 @@ impl Config {
             } => {
                 if *is_dark && !dark.is_empty() {
                     Some(dark.clone())
-                } else if !is_dark && !dark.is_empty() {
+                } else if !is_dark && !light.is_empty() {
                     Some(light.clone())
                 } else {
                     None
                 }
-                // if *high_contrast {
-                //     if let Some(palette) = palette.take() {
-                //         // TODO enforce high contrast constraints
-                //         *palette = palette.to_high_contrast();
-                //         todo!()
-                //     }
-                // }
             }
-            Config::Static { name, .. } => Some(name.clone()),
+            Mode::Static { name } => Some(name.clone()),
+        }
+    }
+
+    /// get the id of the explicitly selected high contrast theme, if high contrast is active
+    pub fn high_contrast_id(&self) -> Option<String> {
+        match &self.mode {
+            Mode::DarkLight {
+                is_high_contrast: true,
+                is_dark: true,
+                high_contrast_dark: name,
+                ..
+            }
+            | Mode::DarkLight {
+                is_high_contrast: true,
+                is_dark: false,
+                high_contrast_light: name,
+                ..
+            } if !name.is_empty() => Some(name.clone()),
+            _ => None,
         }
     }
 
-    pub fn get_active(&self) -> anyhow::Result<ColorOverrides> {
-        let active = match self.active_name() {
-            Some(n) => n,
-            _ => anyhow::bail!("No configured active overrides"),
+    /// get the color overrides for the active theme, using the variant that matches the config
+    ///
+    /// An explicitly selected high contrast theme is used as is, otherwise
+    /// the active theme is made high contrast if necessary.
+    pub fn get_active(&self) -> Result<ColorOverrides> {
+        self.get_active_in(&self.theme_store()?)
+    }
+
+    /// like [`Config::get_active`], loading the active theme from `store`
+    pub fn get_active_in(&self, store: &dyn ThemeStore) -> Result<ColorOverrides> {
+        let active = match self.active_id() {
+            Some(n) if !n.is_empty() => n,
+            _ => return Err(Error::NoActiveTheme),
         };
-        let css_path: PathBuf = [NAME, THEME_DIR].iter().collect();
-        let css_dirs = xdg::BaseDirectories::with_prefix(css_path)?;
-        let active_theme_path = match css_dirs.find_data_file(format!("{active}.ron")) {
-            Some(p) => p,
-            _ => anyhow::bail!("Could not find theme"),
+        let theme = store.get(&active)?;
+        let colors = match &self.mode {
+            Mode::DarkLight { is_dark, .. } if self.high_contrast_id().is_some() => theme
+                .get(VariantSlot::new(*is_dark, true))
+                .cloned()
+                .or_else(|| theme.resolve(*is_dark, false)),
+            Mode::DarkLight {
+                is_dark,
+                is_high_contrast,
+                ..
+            } => theme.resolve(*is_dark, *is_high_contrast),
+            Mode::Static { .. } => theme.slots().next().and_then(|s| theme.get(s).cloned()),
         };
-        let active_theme_file = File::open(active_theme_path)?;
-        let reader = BufReader::new(active_theme_file);
-
-        let colors = ron::de::from_reader::<_, ColorOverrides>(reader)?;
-        Ok(colors)
+        match colors {
+            Some(c) => Ok(c),
+            None => Err(Error::EmptyTheme { name: active }),
+        }
     }
 
     pub fn set_active_light(new: &str) -> Result<()> {
-        let mut self_ = Self::load()?;
-        match self_ {
-            Config::DarkLight { ref mut light, .. } => {
+        Self::update(|self_| match self_.mode {
+            Mode::DarkLight { ref mut light, .. } => {
                 *light = new.to_string();
             }
-            Config::Static { ref mut name, .. } => {
+            Mode::Static { ref mut name } => {
                 *name = new.to_string();
             }
-        };
-        self_.save()
+        })?;
+        Ok(())
     }
 
     pub fn set_active_dark(new: &str) -> Result<()> {
-        let mut self_ = Self::load()?;
-        match self_ {
-            Config::DarkLight { ref mut dark, .. } => {
+        Self::update(|self_| match self_.mode {
+            Mode::DarkLight { ref mut dark, .. } => {
                 *dark = new.to_string();
             }
-            Config::Static { ref mut name, .. } => {
+            Mode::Static { ref mut name } => {
                 *name = new.to_string();
             }
-        };
-        self_.save()
+        })?;
+        Ok(())
     }
 }
 
+/// the themes are referenced by the ids derived from their names, see [`slugify`]
 impl From<(ColorOverrides, ColorOverrides)> for Config {
     fn from((light, dark): (ColorOverrides, ColorOverrides)) -> Self {
-        Self::DarkLight {
-            is_dark: true,
-            is_high_contrast: false,
-            light: light.name,
-            dark: dark.name,
-        }
+        Self::new_dark_light(true, false, slugify(&light.name), slugify(&dark.name))
     }
 }
 
 impl From<ColorOverrides> for Config {
     fn from(t: ColorOverrides) -> Self {
-        Self::Static {
-            name: t.name,
-            apply_all: false,
+        Self::new_static(slugify(&t.name), false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AppliedState, BlockPosition, Config, Mode, CONFIG_VERSION};
+    use crate::{
+        colors::ColorOverrides,
+        error::Error,
+        gtk_css::IMPORT,
+        store::MemoryThemeStore,
+        test_util::TempDir,
+        theme::{Theme, VariantSlot},
+    };
+
+    #[test]
+    fn high_contrast_slots() {
+        let mut config = Config::new_dark_light(false, true, "day".into(), "night".into());
+        assert_eq!(config.active_id().as_deref(), Some("day"));
+        assert_eq!(config.high_contrast_id(), None);
+
+        if let Mode::DarkLight {
+            ref mut high_contrast_light,
+            ..
+        } = config.mode
+        {
+            *high_contrast_light = "bright day".into();
         }
+        assert_eq!(config.active_id().as_deref(), Some("bright day"));
+    }
+
+    #[test]
+    fn legacy_layout() {
+        // written before the high contrast slots existed
+        let (config, migrated_from) = Config::from_ron(
+            "DarkLight(is_high_contrast: true, is_dark: false, light: \"day\", dark: \"night\")",
+        )
+        .unwrap();
+        assert_eq!(migrated_from, Some(1));
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(!config.apply_all);
+        assert_eq!(config.active_id().as_deref(), Some("day"));
+
+        let (config, migrated_from) =
+            Config::from_ron("Static(name: \"noon\", apply_all: true)").unwrap();
+        assert_eq!(migrated_from, Some(1));
+        assert!(config.apply_all);
+        assert_eq!(config.active_id().as_deref(), Some("noon"));
+
+        let ron = ron::ser::to_string(&config).unwrap();
+        let (config, migrated_from) = Config::from_ron(&ron).unwrap();
+        assert_eq!(migrated_from, None);
+        assert!(config.apply_all);
+    }
+
+    #[test]
+    fn unknown_and_missing_fields() {
+        let (config, migrated_from) = Config::from_ron(
+            "(version: 3, mode: Static(name: \"noon\", accent: \"blue\"), future_option: [1, 2])",
+        )
+        .unwrap();
+        assert_eq!(migrated_from, None);
+        assert_eq!(config.version, 3);
+        assert!(!config.apply_all);
+        assert_eq!(config.active_id().as_deref(), Some("noon"));
+
+        // written before the version field existed
+        let (config, migrated_from) = Config::from_ron("(mode: DarkLight(is_dark: true))").unwrap();
+        assert_eq!(migrated_from, None);
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.active_id(), None);
+    }
+
+    #[test]
+    fn active_overrides() {
+        let mut theme = Theme {
+            name: "Day and Night".into(),
+            ..Default::default()
+        };
+        theme.set(VariantSlot::Light, Some(ColorOverrides::light_default()));
+        theme.set(VariantSlot::Dark, Some(ColorOverrides::dark_default()));
+        let mut store = MemoryThemeStore::default();
+        store.add_system(theme);
+
+        let mut config =
+            Config::new_dark_light(true, false, "day-and-night".into(), "day-and-night".into());
+        let active = config.get_active_in(&store).unwrap();
+        assert_eq!(
+            active.window_bg_color,
+            ColorOverrides::dark_default().window_bg_color
+        );
+        assert_eq!(active.name, "Day and Night");
+
+        config.mode = Mode::Static {
+            name: "missing".into(),
+        };
+        assert!(matches!(
+            config.get_active_in(&store),
+            Err(Error::ThemeNotFound { name }) if name == "missing"
+        ));
+        config.mode = Mode::Static {
+            name: String::new(),
+        };
+        assert!(matches!(
+            config.get_active_in(&store),
+            Err(Error::NoActiveTheme)
+        ));
+    }
+
+    #[test]
+    fn from_overrides() {
+        let named = |name: &str| ColorOverrides {
+            name: name.into(),
+            ..Default::default()
+        };
+        let config = Config::from(named("Solarized Dark"));
+        assert_eq!(config.active_id().as_deref(), Some("solarized-dark"));
+        let config = Config::from((named("My Light"), named("My Dark")));
+        assert!(matches!(
+            config.mode,
+            Mode::DarkLight { ref light, ref dark, .. } if light == "my-light" && dark == "my-dark"
+        ));
+    }
+
+    #[test]
+    fn newer_version_is_kept() {
+        let dir = TempDir::new("newer-config");
+        let path = dir.join("config.ron");
+        let newer = format!(
+            "(version: {}, apply_all: true, future_field: 1)",
+            CONFIG_VERSION + 1
+        );
+        std::fs::write(&path, &newer).unwrap();
+
+        // it can be read, but not saved over
+        let (config, _) = Config::from_ron(&newer).unwrap();
+        assert!(config.apply_all);
+        assert!(matches!(
+            Config::update_at(&path, |c| c.apply_all = false),
+            Err(Error::NewerVersion { version, .. }) if version == CONFIG_VERSION + 1
+        ));
+        assert!(matches!(
+            Config::default().save_at(&path),
+            Err(Error::NewerVersion { .. })
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), newer);
+    }
+
+    #[test]
+    fn unimport_shrinks_gtk_css() {
+        let dir = TempDir::new("unimport");
+        let (gtk_css, backup) = (dir.join("gtk.css"), dir.join("gtk.css.bak.ron"));
+        let user_css = "window { margin: 0; }\n";
+        Config::set_gtk_css_block(&gtk_css, &backup, Some(&[IMPORT]), BlockPosition::Top).unwrap();
+        std::fs::write(
+            &gtk_css,
+            format!("{}{user_css}", std::fs::read_to_string(&gtk_css).unwrap()),
+        )
+        .unwrap();
+
+        Config::set_gtk_css_block(&gtk_css, &backup, None, BlockPosition::Top).unwrap();
+        // the file shrinks without leftovers of the removed block
+        assert_eq!(std::fs::read_to_string(&gtk_css).unwrap(), user_css);
+    }
+
+    #[test]
+    fn gtk_css_backup() {
+        let dir = TempDir::new("backup");
+        let (gtk_css, backup) = (
+            dir.join("gtk.css"),
+            dir.join("backup").join("gtk.css.bak.ron"),
+        );
+        let user_css = "window { margin: 0; }\n";
+        std::fs::write(&gtk_css, user_css).unwrap();
+
+        Config::set_gtk_css_block(&gtk_css, &backup, Some(&[IMPORT]), BlockPosition::Top).unwrap();
+        Config::set_gtk_css_block(&gtk_css, &backup, Some(&[IMPORT]), BlockPosition::Bottom)
+            .unwrap();
+        // the backup is taken before the first change only
+        let saved: Option<String> =
+            ron::from_str(&std::fs::read_to_string(&backup).unwrap()).unwrap();
+        assert_eq!(saved.as_deref(), Some(user_css));
+
+        Config::restore_gtk_css(&gtk_css, &backup).unwrap();
+        assert_eq!(std::fs::read_to_string(&gtk_css).unwrap(), user_css);
+        assert!(!backup.exists());
+
+        // a gtk.css created by the editor is removed again
+        std::fs::remove_file(&gtk_css).unwrap();
+        Config::set_gtk_css_block(&gtk_css, &backup, Some(&[IMPORT]), BlockPosition::Top).unwrap();
+        Config::restore_gtk_css(&gtk_css, &backup).unwrap();
+        assert!(!gtk_css.exists());
+
+        // an empty gtk.css is kept
+        std::fs::write(&gtk_css, "").unwrap();
+        Config::set_gtk_css_block(&gtk_css, &backup, Some(&[IMPORT]), BlockPosition::Top).unwrap();
+        Config::restore_gtk_css(&gtk_css, &backup).unwrap();
+        assert_eq!(std::fs::read_to_string(&gtk_css).unwrap(), "");
+    }
+
+    #[test]
+    fn restore_applied_state() {
+        let dir = TempDir::new("applied");
+        let (config, cosmic_css) = (dir.join("config.ron"), dir.join("cosmic.css"));
+        std::fs::write(&config, "(version: 2)").unwrap();
+
+        let state = AppliedState::capture(vec![config.clone(), cosmic_css.clone()]).unwrap();
+        std::fs::write(&config, "(version: 2, apply_all: true)").unwrap();
+        std::fs::write(&cosmic_css, "@define-color window_bg_color #ff00ff;").unwrap();
+
+        state.restore().unwrap();
+        assert_eq!(std::fs::read_to_string(&config).unwrap(), "(version: 2)");
+        assert!(!cosmic_css.exists());
+    }
+
+    #[test]
+    fn concurrent_updates() {
+        let dir = TempDir::new("update");
+        let path = dir.join("config.ron");
+
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for i in 0..4 {
+                        Config::update_at(&path, |c| {
+                            c.theme_paths.push(format!("{writer}-{i}").into());
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        // no update was lost
+        let (config, _) = Config::from_ron(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(config.theme_paths.len(), 32);
+
+        // changes made without the lock since the config was read are kept as well
+        let config = Config::update_at(&path, |c| {
+            if c.theme_paths.len() == 32 {
+                std::fs::write(&path, "(apply_all: true)").unwrap();
+            }
+            c.gtk_css_position = BlockPosition::Top;
+        })
+        .unwrap();
+        assert!(config.apply_all);
+        assert_eq!(config.gtk_css_position, BlockPosition::Top);
+    }
+
+    /// set to the config path for the writers started by `concurrent_processes`
+    const WRITER_ENV: &str = "USER_COLORS_TEST_CONFIG";
+
+    #[test]
+    fn concurrent_processes() {
+        // started again by the test below as one of the writers
+        if let Some(path) = std::env::var_os(WRITER_ENV) {
+            for i in 0..8 {
+                Config::update_at(path.as_ref(), |c| {
+                    c.theme_paths
+                        .push(format!("{}-{i}", std::process::id()).into());
+                })
+                .unwrap();
+            }
+            return;
+        }
+
+        let dir = TempDir::new("processes");
+        let path = dir.join("config.ron");
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                std::process::Command::new(std::env::current_exe().unwrap())
+                    .args(["--exact", "config::tests::concurrent_processes"])
+                    .env(WRITER_ENV, &path)
+                    .stdout(std::process::Stdio::null())
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+        for mut writer in writers {
+            assert!(writer.wait().unwrap().success());
+        }
+
+        // no update was lost, the processes only share the lock file
+        let (config, _) = Config::from_ron(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(config.theme_paths.len(), 32);
     }
 }
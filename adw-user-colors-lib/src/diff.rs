@@ -0,0 +1,304 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+//! Comparing and merging versions of a theme
+
+use palette::{ColorDifference, FromColor, Lab, Srgb};
+
+use crate::{
+    colors::{ColorOverrides, KEYS},
+    theme::{Theme, VariantSlot},
+};
+
+/// How a single key differs between two versions
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyChange {
+    Added {
+        after: String,
+    },
+    Removed {
+        before: String,
+    },
+    Changed {
+        before: String,
+        after: String,
+        /// CIEDE2000 difference, `None` if either color can't be parsed
+        delta_e: Option<f32>,
+    },
+}
+
+/// a key which differs between two versions
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyDiff {
+    pub key: &'static str,
+    pub change: KeyChange,
+}
+
+/// the keys which differ between `before` and `after`, in the order of [`KEYS`] followed by
+/// `custom_css`
+pub fn diff(before: &ColorOverrides, after: &ColorOverrides) -> Vec<KeyDiff> {
+    let custom_css = |o: &ColorOverrides| o.custom_css.clone();
+    KEYS.iter()
+        .map(|&key| (key, before.get_key(key), after.get_key(key)))
+        .chain([("custom_css", custom_css(before), custom_css(after))])
+        .filter_map(|(key, before, after)| {
+            let change = match (before, after) {
+                (None, Some(after)) => KeyChange::Added { after },
+                (Some(before), None) => KeyChange::Removed { before },
+                (Some(before), Some(after)) if before != after => KeyChange::Changed {
+                    delta_e: delta_e(&before, &after),
+                    before,
+                    after,
+                },
+                _ => return None,
+            };
+            Some(KeyDiff { key, change })
+        })
+        .collect()
+}
+
+/// perceptual difference of two css colors, ignoring alpha
+///
+/// Around 1 is barely noticeable, black and white are 100 apart.
+pub fn delta_e(a: &str, b: &str) -> Option<f32> {
+    let lab = |c: &str| {
+        let c = csscolorparser::parse(c).ok()?;
+        Some(Lab::from_color(Srgb::new(
+            c.r as f32, c.g as f32, c.b as f32,
+        )))
+    };
+    Some(lab(a)?.get_color_difference(&lab(b)?))
+}
+
+/// a key which was changed differently in both versions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub key: &'static str,
+    pub base: Option<String>,
+    pub local: Option<String>,
+    pub upstream: Option<String>,
+}
+
+/// the result of a three-way merge
+#[derive(Debug, Clone, PartialEq)]
+pub struct Merge<T, C = Conflict> {
+    /// the merged version, conflicting keys keep the local value
+    pub merged: T,
+    pub conflicts: Vec<C>,
+}
+
+/// merge the changes made locally and upstream since their common ancestor `base`
+///
+/// Keys changed on one side take that change. Keys changed differently on both sides keep the
+/// local value and are reported as conflicts. The name is kept from `local`.
+pub fn merge(
+    base: &ColorOverrides,
+    local: &ColorOverrides,
+    upstream: &ColorOverrides,
+) -> Merge<ColorOverrides> {
+    let mut merged = local.clone();
+    let mut conflicts = Vec::new();
+
+    if local.variant == base.variant {
+        merged.variant = upstream.variant;
+    } else if upstream.variant != base.variant && upstream.variant != local.variant {
+        conflicts.push(Conflict {
+            key: "variant",
+            base: base.variant.map(|v| format!("{v:?}")),
+            local: local.variant.map(|v| format!("{v:?}")),
+            upstream: upstream.variant.map(|v| format!("{v:?}")),
+        });
+    }
+
+    if local.custom_css == base.custom_css {
+        merged.custom_css = upstream.custom_css.clone();
+    } else if upstream.custom_css != base.custom_css && upstream.custom_css != local.custom_css {
+        conflicts.push(Conflict {
+            key: "custom_css",
+            base: base.custom_css.clone(),
+            local: local.custom_css.clone(),
+            upstream: upstream.custom_css.clone(),
+        });
+    }
+
+    for key in KEYS {
+        let (base, local, upstream) =
+            (base.get_key(key), local.get_key(key), upstream.get_key(key));
+        if local == base {
+            if let Some(field) = merged.key_mut(key) {
+                *field = upstream;
+            }
+        } else if upstream != base && upstream != local {
+            conflicts.push(Conflict {
+                key,
+                base,
+                local,
+                upstream,
+            });
+        }
+    }
+    Merge { merged, conflicts }
+}
+
+/// merge each variant of a theme like [`merge`], conflicts are tagged with their variant
+///
+/// A variant added on one side is kept, a variant removed on one side is removed unless it was
+/// changed on the other. Name, id and metadata are kept from `local`.
+pub fn merge_theme(
+    base: &Theme,
+    local: &Theme,
+    upstream: &Theme,
+) -> Merge<Theme, (VariantSlot, Conflict)> {
+    let mut merged = local.clone();
+    let mut conflicts = Vec::new();
+    let empty = ColorOverrides::default();
+
+    for slot in VariantSlot::ALL {
+        let overrides = match (base.get(slot), local.get(slot), upstream.get(slot)) {
+            (_, None, None) => None,
+            (None, Some(l), None) => Some(l.clone()),
+            (None, None, Some(u)) => Some(u.clone()),
+            (Some(b), Some(l), None) => changed(b, l).then(|| l.clone()),
+            (Some(b), None, Some(u)) => changed(b, u).then(|| u.clone()),
+            (b, Some(l), Some(u)) => {
+                let m = merge(b.unwrap_or(&empty), l, u);
+                conflicts.extend(m.conflicts.into_iter().map(|c| (slot, c)));
+                Some(m.merged)
+            }
+        };
+        merged.set(slot, overrides);
+    }
+    Merge { merged, conflicts }
+}
+
+fn changed(before: &ColorOverrides, after: &ColorOverrides) -> bool {
+    before.variant != after.variant || !diff(before, after).is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{delta_e, diff, merge, merge_theme, KeyChange};
+    use crate::{
+        colors::ColorOverrides,
+        theme::{Theme, VariantSlot},
+    };
+
+    fn overrides(colors: &[(&str, &str)]) -> ColorOverrides {
+        let mut overrides = ColorOverrides::default();
+        for (key, value) in colors {
+            overrides.set_key(key, Some(value.to_string())).unwrap();
+        }
+        overrides
+    }
+
+    #[test]
+    fn keys_and_delta_e() {
+        let before = overrides(&[("accent_color", "#3584e4"), ("window_bg_color", "#ffffff")]);
+        let after = overrides(&[("window_bg_color", "#000000"), ("view_bg_color", "#fafafa")]);
+        let changes: Vec<_> = diff(&before, &after)
+            .into_iter()
+            .map(|d| (d.key, d.change))
+            .collect();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(
+            changes[0],
+            (
+                "accent_color",
+                KeyChange::Removed {
+                    before: "#3584e4".into()
+                }
+            )
+        );
+        assert!(matches!(
+            &changes[1],
+            ("window_bg_color", KeyChange::Changed { delta_e: Some(d), .. }) if (d - 100.).abs() < 0.5
+        ));
+        assert!(matches!(
+            &changes[2],
+            ("view_bg_color", KeyChange::Added { .. })
+        ));
+        assert!(diff(&before, &before).is_empty());
+
+        assert!(delta_e("#3584e4", "rgb(53, 132, 228)").unwrap() < 0.01);
+        assert!(delta_e("#3584e4", "#3584e5").unwrap() < 1.);
+        assert_eq!(delta_e("#3584e4", "not a color"), None);
+    }
+
+    #[test]
+    fn three_way() {
+        let base = overrides(&[
+            ("accent_color", "#3584e4"),
+            ("window_bg_color", "#ffffff"),
+            ("view_bg_color", "#ffffff"),
+        ]);
+        let local = overrides(&[
+            ("accent_color", "#e01b24"),
+            ("window_bg_color", "#fafafa"),
+            ("view_bg_color", "#ffffff"),
+        ]);
+        let upstream = overrides(&[
+            ("accent_color", "#33d17a"),
+            ("window_bg_color", "#fafafa"),
+            ("headerbar_bg_color", "#ebebeb"),
+        ]);
+        let merge = merge(&base, &local, &upstream);
+        // changed on both sides, the local value wins
+        assert_eq!(merge.merged.accent_color.as_deref(), Some("#e01b24"));
+        assert_eq!(merge.conflicts.len(), 1);
+        assert_eq!(merge.conflicts[0].key, "accent_color");
+        assert_eq!(merge.conflicts[0].upstream.as_deref(), Some("#33d17a"));
+        // the same change on both sides
+        assert_eq!(merge.merged.window_bg_color.as_deref(), Some("#fafafa"));
+        // changed upstream only
+        assert_eq!(merge.merged.view_bg_color, None);
+        assert_eq!(merge.merged.headerbar_bg_color.as_deref(), Some("#ebebeb"));
+    }
+
+    #[test]
+    fn theme_variants() {
+        let mut base = Theme::default();
+        base.set_name("Sunrise");
+        base.set(
+            VariantSlot::Light,
+            Some(overrides(&[("accent_color", "#3584e4")])),
+        );
+        base.set(
+            VariantSlot::Dark,
+            Some(overrides(&[("accent_color", "#78aeed")])),
+        );
+
+        let mut local = base.clone();
+        local.set_name("My sunrise");
+        local.set(
+            VariantSlot::Light,
+            Some(overrides(&[("accent_color", "#e01b24")])),
+        );
+        local.set(VariantSlot::Dark, None);
+
+        let mut upstream = base.clone();
+        upstream.set(
+            VariantSlot::Light,
+            Some(overrides(&[("accent_color", "#33d17a")])),
+        );
+        upstream.set(
+            VariantSlot::HighContrastDark,
+            Some(overrides(&[("accent_color", "#99c1f1")])),
+        );
+
+        let merge = merge_theme(&base, &local, &upstream);
+        assert_eq!(merge.merged.name, "My sunrise");
+        assert_eq!(
+            merge.merged.slots().collect::<Vec<_>>(),
+            [VariantSlot::Light, VariantSlot::HighContrastDark]
+        );
+        assert_eq!(merge.conflicts.len(), 1);
+        assert_eq!(merge.conflicts[0].0, VariantSlot::Light);
+
+        // a removed variant which was changed on the other side is kept
+        upstream.set(
+            VariantSlot::Dark,
+            Some(overrides(&[("accent_color", "#1a5fb4")])),
+        );
+        let merge = merge_theme(&base, &local, &upstream);
+        assert!(merge.merged.dark.is_some());
+    }
+}
@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{config::CONFIG_NAME, NAME};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors of the library, to let applications react differently to them
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Could not find the theme {name}")]
+    ThemeNotFound { name: String },
+    #[error("The theme {name} has no colors")]
+    EmptyTheme { name: String },
+    #[error("Invalid theme id {id}")]
+    InvalidId { id: String },
+    #[error("A theme with the id {id} already exists")]
+    ThemeExists { id: String },
+    #[error("The theme {id} is read-only")]
+    ReadOnly { id: String },
+    #[error("There is no other version of {id}")]
+    NoOtherVersion { id: String },
+    #[error("The revision of {id} from {time} no longer exists")]
+    RevisionNotFound { id: String, time: u64 },
+    /// a RON file or string which can't be parsed, `path` is `None` for strings
+    #[error(
+        "{}{line}:{col}: {msg}",
+        path.as_ref().map(|p| format!("{}:", p.display())).unwrap_or_default()
+    )]
+    Parse {
+        path: Option<PathBuf>,
+        line: usize,
+        col: usize,
+        msg: String,
+    },
+    #[error("Could not write RON: {0}")]
+    Serialize(#[from] ron::Error),
+    #[error("Invalid color {value} for {key}")]
+    InvalidColor { key: String, value: String },
+    #[error("Line {line} of the custom css: {reason}")]
+    InvalidCustomCss { line: usize, reason: &'static str },
+    #[error("Unknown color {key}")]
+    UnknownKey { key: String },
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    /// a file written by a newer version of the library, which would lose data if it was saved
+    #[error("{} is from a newer version of {NAME} (version {version}) and is left unchanged", path.display())]
+    NewerVersion { path: PathBuf, version: u32 },
+    /// a theme read from a file of a newer version, saving it would lose data
+    #[error(
+        "The theme {id} is from a newer version of {NAME} (version {version}) and can't be saved"
+    )]
+    NewerTheme { id: String, version: u32 },
+    #[error("No active theme is configured")]
+    NoActiveTheme,
+    #[error("{CONFIG_NAME}.ron kept changing while it was updated")]
+    ConcurrentUpdate,
+    #[error("gtk.css has a malformed block managed by {NAME}: {reason}")]
+    GtkCssMalformed { reason: &'static str },
+    #[error("The block managed by {NAME} in gtk.css was edited by hand. Remove the block or undo the changes to let {NAME} manage it again")]
+    GtkCssEdited,
+    #[error(transparent)]
+    Xdg(#[from] xdg::BaseDirectoriesError),
+    #[cfg(feature = "notify")]
+    #[error(transparent)]
+    Watch(#[from] notify::Error),
+}
+
+impl Error {
+    /// wrap an io error with the path it happened on, for `map_err`
+    pub(crate) fn io(path: impl AsRef<Path>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.as_ref().to_path_buf();
+        move |source| Error::Io { path, source }
+    }
+
+    /// the position and message of a RON error, `path` is the file which was parsed
+    pub(crate) fn parse(path: Option<&Path>, err: ron::error::SpannedError) -> Self {
+        Error::Parse {
+            path: path.map(Path::to_path_buf),
+            line: err.position.line,
+            col: err.position.col,
+            msg: err.code.to_string(),
+        }
+    }
+
+    /// set the path of a [`Error::Parse`] which has none
+    pub(crate) fn in_file(self, file: &Path) -> Self {
+        match self {
+            Error::Parse {
+                path: None,
+                line,
+                col,
+                msg,
+            } => Error::Parse {
+                path: Some(file.to_path_buf()),
+                line,
+                col,
+                msg,
+            },
+            err => err,
+        }
+    }
+}
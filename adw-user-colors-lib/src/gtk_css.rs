@@ -0,0 +1,228 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+//! The block of gtk.css managed by the editor
+//!
+//! The block is delimited by comments, and the begin comment holds a checksum of the content,
+//! so changes made by hand are detected instead of being overwritten.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// line which applies the overrides to all GTK4 applications
+pub const IMPORT: &str = "@import url(\"cosmic.css\");";
+
+const BEGIN: &str = "/* BEGIN com.system76.UserColorEditor";
+const BEGIN_SUFFIX: &str = "- managed block, do not edit */";
+const END: &str = "/* END com.system76.UserColorEditor */";
+
+/// Where the managed block is placed in gtk.css
+///
+/// Later rules override earlier ones, so at the top the user's own rules win.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum BlockPosition {
+    Top,
+    #[default]
+    Bottom,
+}
+
+/// the managed block found in a gtk.css
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// line of the begin comment
+    pub start: usize,
+    /// line of the end comment
+    pub end: usize,
+    pub content: Vec<String>,
+    /// whether the content was changed since it was written
+    pub edited: bool,
+}
+
+/// find the managed block in `css`
+pub fn find(css: &str) -> Result<Option<Block>> {
+    let lines: Vec<&str> = css.lines().collect();
+    let mut starts = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| l.trim_start().starts_with(BEGIN));
+    let (start, begin) = match starts.next() {
+        Some((start, begin)) => (start, begin.trim()),
+        None => {
+            if lines.iter().any(|l| l.trim() == END) {
+                return Err(Error::GtkCssMalformed {
+                    reason: "the end has no beginning",
+                });
+            }
+            return Ok(None);
+        }
+    };
+    if starts.next().is_some() {
+        return Err(Error::GtkCssMalformed {
+            reason: "there is more than one block",
+        });
+    }
+    let end = match lines[start..].iter().position(|l| l.trim() == END) {
+        Some(len) => start + len,
+        None => {
+            return Err(Error::GtkCssMalformed {
+                reason: "the block is not terminated",
+            })
+        }
+    };
+
+    let content: Vec<String> = lines[start + 1..end]
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+    let checksum = begin
+        .strip_prefix(BEGIN)
+        .and_then(|rest| rest.strip_suffix(BEGIN_SUFFIX))
+        .map(str::trim);
+    let edited = checksum != Some(&self::checksum(&content));
+    Ok(Some(Block {
+        start,
+        end,
+        content,
+        edited,
+    }))
+}
+
+/// replace the managed block in `css` with `content`, or remove it if there is no content
+///
+/// Imports of the overrides written by older versions outside of the block are removed.
+/// Returns `None` if nothing changed, and fails if the block was edited by hand.
+pub fn set_block(
+    css: &str,
+    content: Option<&[&str]>,
+    position: BlockPosition,
+) -> Result<Option<String>> {
+    replace_block(css, content, position, false)
+}
+
+/// remove the managed block from `css`, even if it was edited by hand
+pub fn remove_block(css: &str) -> Result<Option<String>> {
+    replace_block(css, None, BlockPosition::default(), true)
+}
+
+fn replace_block(
+    css: &str,
+    content: Option<&[&str]>,
+    position: BlockPosition,
+    force: bool,
+) -> Result<Option<String>> {
+    let block = find(css)?;
+    if let (Some(Block { edited: true, .. }), false) = (&block, force) {
+        return Err(Error::GtkCssEdited);
+    }
+
+    let mut lines: Vec<String> = css.lines().map(|l| l.to_string()).collect();
+    if let Some(block) = &block {
+        lines.drain(block.start..=block.end);
+    }
+    // unmanaged imports of older versions
+    lines.retain(|l| l.trim() != IMPORT);
+
+    if let Some(content) = content {
+        let content: Vec<String> = content.iter().map(|l| l.to_string()).collect();
+        let mut block = Vec::with_capacity(content.len() + 2);
+        block.push(format!("{BEGIN} {} {BEGIN_SUFFIX}", checksum(&content)));
+        block.extend(content);
+        block.push(END.to_string());
+        match position {
+            BlockPosition::Top => {
+                lines.splice(0..0, block);
+            }
+            BlockPosition::Bottom => lines.extend(block),
+        }
+    }
+
+    let mut new_css = lines.join("\n");
+    if !new_css.is_empty() {
+        new_css.push('\n');
+    }
+    Ok((new_css != css).then_some(new_css))
+}
+
+/// FNV-1a of the content lines, short enough to read in the begin comment
+fn checksum(content: &[String]) -> String {
+    let hash = content
+        .join("\n")
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, remove_block, set_block, BlockPosition, IMPORT};
+    use crate::error::Error;
+
+    const USER_CSS: &str = "window {\n  margin: 0;\n}\n";
+
+    #[test]
+    fn insert_update_remove() {
+        let bottom = set_block(USER_CSS, Some(&[IMPORT]), BlockPosition::Bottom)
+            .unwrap()
+            .unwrap();
+        assert!(bottom.starts_with(USER_CSS));
+        let block = find(&bottom).unwrap().unwrap();
+        assert_eq!(block.content, vec![IMPORT.to_string()]);
+        assert!(!block.edited);
+        assert_eq!(
+            set_block(&bottom, Some(&[IMPORT]), BlockPosition::Bottom).unwrap(),
+            None
+        );
+
+        // moving the block keeps the rest of the file
+        let top = set_block(&bottom, Some(&[IMPORT]), BlockPosition::Top)
+            .unwrap()
+            .unwrap();
+        assert!(top.ends_with(USER_CSS));
+        assert_eq!(find(&top).unwrap().unwrap().start, 0);
+
+        let removed = set_block(&top, None, BlockPosition::Top).unwrap().unwrap();
+        assert_eq!(removed, USER_CSS);
+        assert_eq!(set_block(USER_CSS, None, BlockPosition::Top).unwrap(), None);
+    }
+
+    #[test]
+    fn legacy_import() {
+        let css = format!("{USER_CSS}\n{IMPORT}\n");
+        let migrated = set_block(&css, Some(&[IMPORT]), BlockPosition::Bottom)
+            .unwrap()
+            .unwrap();
+        assert_eq!(migrated.matches(IMPORT).count(), 1);
+        assert!(find(&migrated).unwrap().is_some());
+
+        // imports in the middle of other lines are not ours to remove
+        let inline = format!("/* {IMPORT} */\n");
+        assert_eq!(
+            set_block(&inline, None, BlockPosition::Bottom).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn hand_edited() {
+        let css = set_block(USER_CSS, Some(&[IMPORT]), BlockPosition::Bottom)
+            .unwrap()
+            .unwrap();
+        let edited = css.replace(IMPORT, "@import url(\"other.css\");");
+        assert!(find(&edited).unwrap().unwrap().edited);
+        assert!(matches!(
+            set_block(&edited, Some(&[IMPORT]), BlockPosition::Bottom),
+            Err(Error::GtkCssEdited)
+        ));
+        assert!(set_block(&edited, None, BlockPosition::Bottom).is_err());
+        assert_eq!(remove_block(&edited).unwrap().as_deref(), Some(USER_CSS));
+
+        let unterminated = css
+            .lines()
+            .take(css.lines().count() - 1)
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(find(&unterminated).is_err());
+    }
+}
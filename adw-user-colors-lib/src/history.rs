@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+//! Earlier versions of themes, kept whenever a theme file is overwritten
+
+use std::{
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    atomic,
+    error::{Error, Result},
+    store::ThemeStore,
+    theme::{is_valid_id, Theme},
+    NAME,
+};
+
+/// revisions kept for each theme, older ones are deleted
+pub const MAX_REVISIONS: usize = 20;
+const HISTORY_DIR: &str = "history";
+
+/// A snapshot of a theme file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revision {
+    /// id of the theme
+    pub id: String,
+    /// when the snapshot was taken, in milliseconds since the unix epoch
+    pub time: u64,
+    path: PathBuf,
+}
+
+impl Revision {
+    pub fn load(&self) -> Result<Theme> {
+        let ron = fs::read_to_string(&self.path).map_err(Error::io(&self.path))?;
+        let mut theme = Theme::from_ron(&ron).map_err(|err| err.in_file(&self.path))?;
+        theme.id = self.id.clone();
+        Ok(theme)
+    }
+}
+
+/// Revisions of all themes, in a dir with a sub dir for each theme id
+pub struct History {
+    dir: PathBuf,
+    max_revisions: usize,
+}
+
+impl History {
+    /// the history in the XDG data dir
+    pub fn new() -> Result<Self> {
+        let dir = xdg::BaseDirectories::with_prefix(NAME)?
+            .get_data_home()
+            .join(HISTORY_DIR);
+        Ok(Self::with_dir(dir, MAX_REVISIONS))
+    }
+
+    pub fn with_dir(dir: PathBuf, max_revisions: usize) -> Self {
+        Self { dir, max_revisions }
+    }
+
+    /// keep `ron` as the newest revision of the theme with `id`, unless it is the newest already
+    pub fn record(&self, id: &str, ron: &str) -> Result<()> {
+        if !is_valid_id(id) {
+            return Err(Error::InvalidId { id: id.to_string() });
+        }
+        let revisions = self.revisions(id)?;
+        if let Some(newest) = revisions.first() {
+            if fs::read_to_string(&newest.path).ok().as_deref() == Some(ron) {
+                return Ok(());
+            }
+        }
+
+        let dir = self.dir.join(id);
+        fs::create_dir_all(&dir).map_err(Error::io(&dir))?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        // keep the order of revisions taken within the same millisecond
+        let time = revisions
+            .first()
+            .map(|newest| now.max(newest.time + 1))
+            .unwrap_or(now);
+        atomic::write(&dir.join(format!("{time}.ron")), ron)?;
+
+        for old in revisions.iter().skip(self.max_revisions.saturating_sub(1)) {
+            fs::remove_file(&old.path).map_err(Error::io(&old.path))?;
+        }
+        Ok(())
+    }
+
+    /// the revisions of the theme with `id`, newest first
+    pub fn revisions(&self, id: &str) -> Result<Vec<Revision>> {
+        if !is_valid_id(id) {
+            return Ok(Vec::new());
+        }
+        let dir = self.dir.join(id);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(Error::io(&dir)(err)),
+        };
+        let mut revisions: Vec<Revision> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|p| p.extension().map(|e| e == "ron").unwrap_or_default())
+            .filter_map(|path| {
+                let time = path.file_stem()?.to_str()?.parse().ok()?;
+                Some(Revision {
+                    id: id.to_string(),
+                    time,
+                    path,
+                })
+            })
+            .collect();
+        revisions.sort_by_key(|r| std::cmp::Reverse(r.time));
+        Ok(revisions)
+    }
+
+    /// the revision of the theme with `id` from `time`
+    pub fn revision(&self, id: &str, time: u64) -> Result<Revision> {
+        self.revisions(id)?
+            .into_iter()
+            .find(|r| r.time == time)
+            .ok_or_else(|| Error::RevisionNotFound {
+                id: id.to_string(),
+                time,
+            })
+    }
+
+    /// write `revision` back to `store`, which keeps the version it replaces as a revision
+    pub fn restore(store: &mut dyn ThemeStore, revision: &Revision) -> Result<Theme> {
+        let mut theme = revision.load()?;
+        theme.touch();
+        store.put(&mut theme)?;
+        Ok(theme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use crate::{
+        store::{MemoryThemeStore, ThemeStore},
+        test_util::TempDir,
+        theme::Theme,
+    };
+
+    fn theme(name: &str) -> Theme {
+        let mut theme = Theme::default();
+        theme.set_name(name);
+        theme.id = "sunrise".into();
+        theme
+    }
+
+    #[test]
+    fn bounded_and_newest_first() {
+        let dir = TempDir::new("history");
+        let history = History::with_dir(dir.to_path_buf(), 3);
+        for name in ["one", "two", "two", "three", "four"] {
+            history
+                .record("sunrise", &theme(name).to_ron().unwrap())
+                .unwrap();
+        }
+
+        let revisions = history.revisions("sunrise").unwrap();
+        // unchanged versions are kept once, the oldest is dropped
+        let names: Vec<String> = revisions.iter().map(|r| r.load().unwrap().name).collect();
+        assert_eq!(names, ["four", "three", "two"]);
+        assert!(revisions.iter().all(|r| r.load().unwrap().id == "sunrise"));
+        assert!(history.revisions("sunset").unwrap().is_empty());
+
+        let mut store = MemoryThemeStore::default();
+        store.put(&mut theme("five")).unwrap();
+        History::restore(&mut store, &revisions[2]).unwrap();
+        assert_eq!(store.get("sunrise").unwrap().name, "two");
+    }
+}
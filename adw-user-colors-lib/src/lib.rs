@@ -1,9 +1,19 @@
 // SPDX-License-Identifier: MPL-2.0-only
 
+pub mod atomic;
 pub mod colors;
 pub mod config;
+pub mod diff;
+pub mod error;
+pub mod gtk_css;
+pub mod history;
+pub mod lint;
 #[cfg(feature = "notify")]
 pub mod notify;
+pub mod store;
+#[cfg(test)]
+mod test_util;
+pub mod theme;
 
 pub const NAME: &str = "com.system76.UserColorEditor";
 pub const THEME_DIR: &str = "color-overrides";
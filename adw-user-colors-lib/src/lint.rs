@@ -0,0 +1,417 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+//! Checks for themes which parse, but would look broken
+
+use std::{fmt, path::Path};
+
+use crate::{
+    colors::{check_custom_css, is_valid_color, relative_luminance, ColorOverrides, KEYS},
+    diff::delta_e,
+    error::{Error, Result},
+    theme::{Theme, VariantSlot},
+};
+
+/// background and foreground colors which are drawn on top of each other
+///
+/// The first ones are buttons and banners, which have bold labels.
+pub const PAIRS: [(&str, &str); 10] = [
+    ("accent_bg_color", "accent_fg_color"),
+    ("destructive_bg_color", "destructive_fg_color"),
+    ("success_bg_color", "success_fg_color"),
+    ("warning_bg_color", "warning_fg_color"),
+    ("error_bg_color", "error_fg_color"),
+    ("view_bg_color", "view_fg_color"),
+    ("window_bg_color", "window_fg_color"),
+    ("headerbar_bg_color", "headerbar_fg_color"),
+    ("card_bg_color", "card_fg_color"),
+    ("popover_bg_color", "popover_fg_color"),
+];
+
+/// pairs at the start of [`PAIRS`] with bold labels
+const BOLD_PAIRS: usize = 5;
+
+/// WCAG AA contrast for normal and for large or bold text
+pub const MIN_CONTRAST: (f64, f64) = (4.5, 3.0);
+/// WCAG AAA contrast, expected from high contrast variants
+pub const MIN_HIGH_CONTRAST: (f64, f64) = (7.0, 4.5);
+/// ΔE below which a foreground is hard to tell apart from its background
+const NEARLY_IDENTICAL: f32 = 5.0;
+
+/// backgrounds which must be opaque, there is nothing behind them
+const OPAQUE_KEYS: [&str; 2] = ["window_bg_color", "view_bg_color"];
+/// fields of a theme document besides its variants
+const THEME_FIELDS: [&str; 3] = ["version", "name", "metadata"];
+/// fields of a variant besides the colors
+const VARIANT_FIELDS: [&str; 3] = ["name", "variant", "custom_css"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// worth a look, but may be intended
+    Info,
+    /// likely to make some text or widgets hard to see
+    Warning,
+    /// the theme is broken
+    Error,
+}
+
+/// What is wrong with a theme
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// a value which isn't a color, it is left out when the theme is applied
+    InvalidColor { key: String, value: String },
+    /// custom css outside the allowed subset, it is left out when the theme is applied
+    InvalidCustomCss { line: usize, reason: &'static str },
+    /// a field which isn't used, e.g. a misspelled color
+    UnknownKey { key: String },
+    /// a foreground color which is (almost) the same as its background
+    IdenticalPair {
+        bg: &'static str,
+        fg: &'static str,
+        delta_e: f32,
+    },
+    /// a foreground color with too little contrast to its background
+    LowContrast {
+        bg: &'static str,
+        fg: &'static str,
+        ratio: f64,
+        min: f64,
+    },
+    /// a background which shows what is behind the window
+    Translucent { key: &'static str, alpha: f64 },
+    /// one color of a pair is set but not the other, so it is combined with a default
+    MissingCounterpart {
+        key: &'static str,
+        missing: &'static str,
+    },
+}
+
+/// A problem found in a theme
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// the variant with the problem, `None` for problems of the whole document
+    pub slot: Option<VariantSlot>,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::InvalidColor { key, value } => {
+                write!(f, "{key} is set to {value}, which is not a color")
+            }
+            Problem::InvalidCustomCss { line, reason } => {
+                write!(f, "line {line} of the custom css: {reason}")
+            }
+            Problem::UnknownKey { key } => write!(f, "{key} is unknown and ignored"),
+            Problem::IdenticalPair { bg, fg, delta_e } if *delta_e < 1. => {
+                write!(f, "{fg} is the same color as {bg}")
+            }
+            Problem::IdenticalPair { bg, fg, .. } => {
+                write!(f, "{fg} is nearly the same color as {bg}")
+            }
+            Problem::LowContrast { bg, fg, ratio, min } => write!(
+                f,
+                "{fg} has a contrast of {ratio:.1}:1 against {bg}, at least {min}:1 is recommended"
+            ),
+            Problem::Translucent { key, alpha } => {
+                write!(f, "{key} is translucent ({:.0}% opaque)", alpha * 100.)
+            }
+            Problem::MissingCounterpart { key, missing } => {
+                write!(f, "{key} is set without {missing}")
+            }
+        }
+    }
+}
+
+/// check all variants of `theme`, most severe problems first
+pub fn lint(theme: &Theme) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = theme
+        .slots()
+        .flat_map(|slot| {
+            let overrides = theme.get(slot).expect("slots are set");
+            lint_overrides(overrides, slot.is_high_contrast())
+                .into_iter()
+                .map(move |(severity, problem)| Diagnostic {
+                    severity,
+                    slot: Some(slot),
+                    problem,
+                })
+        })
+        .collect();
+    diagnostics.sort_by_key(|d| std::cmp::Reverse(d.severity));
+    diagnostics
+}
+
+/// parse and check a theme document, including fields which are ignored when parsing
+pub fn lint_ron(s: &str) -> Result<Vec<Diagnostic>> {
+    let theme = Theme::from_ron(s)?;
+    let value: ron::Value = ron::from_str(s).map_err(|err| Error::parse(None, err))?;
+    let mut diagnostics = unknown_keys(&value);
+    diagnostics.extend(lint(&theme));
+    diagnostics.sort_by_key(|d| std::cmp::Reverse(d.severity));
+    Ok(diagnostics)
+}
+
+/// like [`lint_ron`], for the theme file at `p`
+pub fn lint_file(p: &dyn AsRef<Path>) -> Result<Vec<Diagnostic>> {
+    let p = p.as_ref();
+    let s = std::fs::read_to_string(p).map_err(Error::io(p))?;
+    lint_ron(&s).map_err(|err| err.in_file(p))
+}
+
+fn lint_overrides(overrides: &ColorOverrides, high_contrast: bool) -> Vec<(Severity, Problem)> {
+    let mut problems = Vec::new();
+    let color = |key: &str| {
+        overrides
+            .get_key(key)
+            .and_then(|c| csscolorparser::parse(&c).ok())
+    };
+
+    for key in KEYS {
+        if let Some(value) = overrides.get_key(key) {
+            if !is_valid_color(&value) {
+                problems.push((
+                    Severity::Error,
+                    Problem::InvalidColor {
+                        key: key.to_string(),
+                        value,
+                    },
+                ));
+            }
+        }
+    }
+
+    if let Some(Err(Error::InvalidCustomCss { line, reason })) =
+        overrides.custom_css.as_deref().map(check_custom_css)
+    {
+        problems.push((Severity::Error, Problem::InvalidCustomCss { line, reason }));
+    }
+
+    for key in OPAQUE_KEYS {
+        if let Some(c) = color(key) {
+            if c.a < 1. {
+                problems.push((Severity::Warning, Problem::Translucent { key, alpha: c.a }));
+            }
+        }
+    }
+
+    // widgets are drawn on the window, translucent backgrounds are blended with it
+    let window = color("window_bg_color").filter(|c| c.a >= 1.);
+    for (i, (bg, fg)) in PAIRS.into_iter().enumerate() {
+        match (overrides.get_key(bg), overrides.get_key(fg)) {
+            (Some(_), None) => problems.push((
+                Severity::Info,
+                Problem::MissingCounterpart {
+                    key: bg,
+                    missing: fg,
+                },
+            )),
+            (None, Some(_)) => problems.push((
+                Severity::Info,
+                Problem::MissingCounterpart {
+                    key: fg,
+                    missing: bg,
+                },
+            )),
+            _ => {}
+        }
+
+        let (bg_color, fg_color) = match (color(bg), color(fg), &window) {
+            (Some(bg_color), Some(fg_color), _) if bg_color.a >= 1. => (bg_color, fg_color),
+            (Some(bg_color), Some(fg_color), Some(window)) => (blend(&bg_color, window), fg_color),
+            // the translucent background is blended with an unknown color
+            _ => continue,
+        };
+        // translucent text is blended with the background as well
+        let fg_color = blend(&fg_color, &bg_color);
+        let delta_e =
+            delta_e(&bg_color.to_hex_string(), &fg_color.to_hex_string()).unwrap_or_default();
+        if delta_e < NEARLY_IDENTICAL {
+            problems.push((Severity::Error, Problem::IdenticalPair { bg, fg, delta_e }));
+            continue;
+        }
+
+        let (l1, l2) = (relative_luminance(&bg_color), relative_luminance(&fg_color));
+        let ratio = (l1.max(l2) + 0.05) / (l1.min(l2) + 0.05);
+        let (normal, bold) = if high_contrast {
+            MIN_HIGH_CONTRAST
+        } else {
+            MIN_CONTRAST
+        };
+        let min = if i < BOLD_PAIRS { bold } else { normal };
+        if ratio < min {
+            problems.push((
+                Severity::Warning,
+                Problem::LowContrast { bg, fg, ratio, min },
+            ));
+        }
+    }
+    problems
+}
+
+/// the opaque color of `top` drawn over the opaque color `bottom`
+fn blend(top: &csscolorparser::Color, bottom: &csscolorparser::Color) -> csscolorparser::Color {
+    let a = top.a;
+    csscolorparser::Color::new(
+        top.r * a + bottom.r * (1. - a),
+        top.g * a + bottom.g * (1. - a),
+        top.b * a + bottom.b * (1. - a),
+        1.,
+    )
+}
+
+/// fields of the document which are ignored when it is parsed
+fn unknown_keys(value: &ron::Value) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let fields = |value: &ron::Value| -> Vec<(String, ron::Value)> {
+        match value {
+            ron::Value::Map(map) => map
+                .iter()
+                .filter_map(|(k, v)| match k {
+                    ron::Value::String(k) => Some((k.clone(), v.clone())),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    };
+    let unknown = |slot: Option<VariantSlot>, key: String| Diagnostic {
+        severity: Severity::Warning,
+        slot,
+        problem: Problem::UnknownKey { key },
+    };
+    let is_variant_field = |k: &str| VARIANT_FIELDS.contains(&k) || KEYS.contains(&k);
+
+    let top = fields(value);
+    let slot_names = VariantSlot::ALL.map(slot_name);
+    // version 0 files have the fields of a single variant at the top level
+    if !top.iter().any(|(k, _)| slot_names.contains(&k.as_str())) {
+        diagnostics.extend(
+            top.into_iter()
+                .filter(|(k, _)| !is_variant_field(k) && !THEME_FIELDS.contains(&k.as_str()))
+                .map(|(k, _)| unknown(None, k)),
+        );
+        return diagnostics;
+    }
+
+    for (key, value) in top {
+        match VariantSlot::ALL.into_iter().find(|s| slot_name(*s) == key) {
+            Some(slot) => {
+                let overrides = match value {
+                    ron::Value::Option(Some(overrides)) => *overrides,
+                    _ => continue,
+                };
+                diagnostics.extend(
+                    fields(&overrides)
+                        .into_iter()
+                        .filter(|(k, _)| !is_variant_field(k))
+                        .map(|(k, _)| unknown(Some(slot), k)),
+                );
+            }
+            None if !THEME_FIELDS.contains(&key.as_str()) => diagnostics.push(unknown(None, key)),
+            None => {}
+        }
+    }
+    diagnostics
+}
+
+/// the field of [`Theme`] holding `slot`
+fn slot_name(slot: VariantSlot) -> &'static str {
+    match slot {
+        VariantSlot::Light => "light",
+        VariantSlot::Dark => "dark",
+        VariantSlot::HighContrastLight => "high_contrast_light",
+        VariantSlot::HighContrastDark => "high_contrast_dark",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, lint_ron, Problem, Severity};
+    use crate::{
+        colors::ColorOverrides,
+        theme::{Theme, VariantSlot},
+    };
+
+    #[test]
+    fn defaults_are_usable() {
+        let mut theme = Theme::from(ColorOverrides::light_default());
+        theme.set(VariantSlot::Dark, Some(ColorOverrides::dark_default()));
+        let diagnostics = lint(&theme);
+        assert!(
+            diagnostics.iter().all(|d| d.severity < Severity::Error),
+            "{diagnostics:?}"
+        );
+        // the content of windows is readable
+        assert!(!diagnostics.iter().any(|d| matches!(
+            d.problem,
+            Problem::LowContrast {
+                bg: "window_bg_color" | "view_bg_color" | "card_bg_color",
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn problems() {
+        let ron = r##"(
+            name: "Broken",
+            extra: 1,
+            dark: Some((
+                window_bg_color: Some("#242424"),
+                window_fg_color: Some("#252525"),
+                view_bg_color: Some("rgba(30, 30, 30, 0.5)"),
+                view_fg_color: Some("#555555"),
+                card_bg_color: Some("blurple"),
+                card_fg_color: Some("#ffffff"),
+                popover_bg_color: Some("#383838"),
+                accent_colour: Some("#3584e4"),
+                custom_css: Some("@import url(\"x.css\");"),
+            )),
+        )"##;
+        let diagnostics = lint_ron(ron).unwrap();
+        let problems: Vec<_> = diagnostics.iter().map(|d| &d.problem).collect();
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert!(problems.contains(&&Problem::InvalidColor {
+            key: "card_bg_color".into(),
+            value: "blurple".into()
+        }));
+        assert!(problems.contains(&&Problem::UnknownKey {
+            key: "extra".into()
+        }));
+        assert!(problems
+            .iter()
+            .any(|p| matches!(p, Problem::InvalidCustomCss { line: 1, .. })));
+        assert!(diagnostics.iter().any(|d| d.slot == Some(VariantSlot::Dark)
+            && d.problem
+                == Problem::UnknownKey {
+                    key: "accent_colour".into()
+                }));
+        assert!(problems.iter().any(|p| matches!(
+            p,
+            Problem::IdenticalPair {
+                fg: "window_fg_color",
+                ..
+            }
+        )));
+        assert!(problems.iter().any(|p| matches!(
+            p,
+            Problem::LowContrast {
+                fg: "view_fg_color",
+                ..
+            }
+        )));
+        assert!(problems.iter().any(|p| matches!(
+            p,
+            Problem::Translucent {
+                key: "view_bg_color",
+                ..
+            }
+        )));
+        assert!(problems.contains(&&Problem::MissingCounterpart {
+            key: "popover_bg_color",
+            missing: "popover_fg_color"
+        }));
+    }
+}
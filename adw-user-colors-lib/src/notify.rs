@@ -1,6 +1,6 @@
 use std::hash::Hash;
 
-use crate::{colors::ColorOverrides, config, NAME};
+use crate::{colors::ColorOverrides, config, error::Result, NAME};
 use futures::{channel::mpsc, SinkExt, StreamExt};
 use iced::{theme::Palette, Color};
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
@@ -64,7 +64,7 @@ pub struct ThemeWatcher {
 }
 #[cfg(feature = "iced")]
 impl ThemeWatcher {
-    pub fn new() -> anyhow::Result<Self> {
+    pub fn new() -> Result<Self> {
         let prev_palette = Self::palette();
         let (mut tx, rx) = mpsc::channel(20);
         let xdg_dirs = xdg::BaseDirectories::with_prefix(NAME)?;
@@ -97,15 +97,15 @@ impl ThemeWatcher {
             .get_active()
             .map(|color_overrides| {
                 (
-                    match config {
-                        config::Config::DarkLight { is_dark, .. } if !is_dark => Palette::LIGHT,
+                    match config.mode {
+                        config::Mode::DarkLight { is_dark, .. } if !is_dark => Palette::LIGHT,
                         _ => Palette::DARK,
                     },
                     color_overrides,
                 )
             })
-            .unwrap_or_else(|_| match config {
-                config::Config::DarkLight {
+            .unwrap_or_else(|_| match config.mode {
+                config::Mode::DarkLight {
                     is_high_contrast,
                     is_dark,
                     ..
@@ -0,0 +1,609 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    atomic,
+    error::{Error, Result},
+    history::History,
+    theme::{is_valid_id, slugify, Theme, THEME_VERSION},
+    NAME, THEME_DIR,
+};
+
+/// Where a theme is stored
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum Origin {
+    /// writable themes of the user
+    User,
+    /// themes in a dir listed in the config, read-only unless the dir is writable
+    Extra { dir: PathBuf, writable: bool },
+    /// read-only themes installed for all users
+    System,
+}
+
+impl Origin {
+    /// whether themes from here can be changed in place
+    pub fn is_writable(&self) -> bool {
+        match self {
+            Origin::User => true,
+            Origin::Extra { writable, .. } => *writable,
+            Origin::System => false,
+        }
+    }
+}
+
+/// Storage of themes, addressed by their id
+///
+/// Themes are looked up in the user dir, then in extra dirs, then in system dirs,
+/// so a user theme shadows a theme with the same id from the other dirs.
+pub trait ThemeStore {
+    /// ids of all themes, sorted
+    fn list(&self) -> Result<Vec<String>>;
+
+    /// where the theme with `id` is stored, `None` if there is no such theme
+    fn origin(&self, id: &str) -> Option<Origin>;
+
+    fn get(&self, id: &str) -> Result<Theme>;
+
+    /// write `theme` in place if its origin is writable, otherwise as a user theme
+    fn write(&mut self, theme: &Theme) -> Result<()>;
+
+    /// delete the theme with `id`, if its origin is writable
+    fn delete(&mut self, id: &str) -> Result<()>;
+
+    /// whether there is a user theme with `id` which shadows a theme from another dir
+    fn shadows(&self, id: &str) -> bool;
+
+    /// store `theme`, assigning an unused id derived from its name if it has none
+    ///
+    /// Themes read from a newer version are refused, their unknown fields would be lost.
+    fn put(&mut self, theme: &mut Theme) -> Result<()> {
+        if theme.version > THEME_VERSION {
+            return Err(Error::NewerTheme {
+                id: theme.id.clone(),
+                version: theme.version,
+            });
+        }
+        if theme.id.is_empty() {
+            theme.id = unique_id(&theme.name, |id| self.origin(id).is_some());
+        } else if !is_valid_id(&theme.id) {
+            return Err(Error::InvalidId {
+                id: theme.id.clone(),
+            });
+        }
+        self.write(theme)
+    }
+
+    /// move the theme with `id` to `new_id`, if its origin is writable
+    fn rename(&mut self, id: &str, new_id: &str) -> Result<()> {
+        check_rename(self, id, new_id)?;
+        let mut theme = self.get(id)?;
+        theme.id = new_id.to_string();
+        self.put(&mut theme)?;
+        self.delete(id)
+    }
+
+    /// delete the user theme with `id`, returning the theme it shadowed
+    fn revert(&mut self, id: &str) -> Result<Theme> {
+        if !self.shadows(id) {
+            return Err(Error::NoOtherVersion { id: id.to_string() });
+        }
+        self.delete(id)?;
+        self.get(id)
+    }
+
+    /// store a copy of the theme with `id` as a new user theme called `name`
+    fn duplicate(&mut self, id: &str, name: &str) -> Result<Theme> {
+        let mut theme = self.get(id)?;
+        theme.id = String::new();
+        theme.set_name(name);
+        theme.metadata.created = None;
+        self.put(&mut theme)?;
+        Ok(theme)
+    }
+}
+
+/// check that the theme with `id` can be renamed to `new_id`, returning its origin
+fn check_rename<S: ThemeStore + ?Sized>(store: &S, id: &str, new_id: &str) -> Result<Origin> {
+    let origin = match store.origin(id) {
+        Some(origin) if origin.is_writable() => origin,
+        Some(_) => return Err(Error::ReadOnly { id: id.to_string() }),
+        None => {
+            return Err(Error::ThemeNotFound {
+                name: id.to_string(),
+            })
+        }
+    };
+    if !is_valid_id(new_id) {
+        return Err(Error::InvalidId {
+            id: new_id.to_string(),
+        });
+    }
+    if store.origin(new_id).is_some() {
+        return Err(Error::ThemeExists {
+            id: new_id.to_string(),
+        });
+    }
+    Ok(origin)
+}
+
+/// the slug of `name`, with a number appended if it is already taken
+pub(crate) fn unique_id(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    let slug = slugify(name);
+    (1..)
+        .map(|i| match i {
+            1 => slug.clone(),
+            i => format!("{slug}-{i}"),
+        })
+        .find(|id| !taken(id))
+        .expect("There are enough numbers.")
+}
+
+/// whether the user may create files in `dir`, writing may still fail e.g. on a full disk
+fn is_writable_dir(dir: &Path) -> bool {
+    if !dir.is_dir() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+        // the mode bits alone don't say whether they apply to the user, access checks
+        // the owner, group and ACLs of the dir
+        match CString::new(dir.as_os_str().as_bytes()) {
+            Ok(dir) => unsafe { libc::access(dir.as_ptr(), libc::W_OK) == 0 },
+            Err(_) => false,
+        }
+    }
+    #[cfg(not(unix))]
+    std::fs::metadata(dir)
+        .map(|m| !m.permissions().readonly())
+        .unwrap_or_default()
+}
+
+/// Themes stored as RON files in the theme dir of the XDG data dirs and in extra dirs
+pub struct XdgThemeStore {
+    xdg_dirs: xdg::BaseDirectories,
+    extra_dirs: Vec<PathBuf>,
+    /// where overwritten versions of themes are kept
+    history: Option<History>,
+}
+
+impl XdgThemeStore {
+    /// a store searching `extra_dirs`, which keeps a [`History`], see [`crate::config::Config::theme_store`]
+    pub fn new(extra_dirs: Vec<PathBuf>) -> Result<Self> {
+        Ok(Self {
+            history: History::new().ok(),
+            ..Self::with_extra_dirs(extra_dirs)?
+        })
+    }
+
+    /// a store searching `extra_dirs`, without history
+    pub fn with_extra_dirs(extra_dirs: Vec<PathBuf>) -> Result<Self> {
+        let ron_path: PathBuf = [NAME, THEME_DIR].iter().collect();
+        Ok(Self {
+            xdg_dirs: xdg::BaseDirectories::with_prefix(ron_path)?,
+            extra_dirs,
+            history: None,
+        })
+    }
+
+    /// create the user theme dir
+    pub fn init(&self) -> Result<PathBuf> {
+        self.xdg_dirs
+            .create_data_directory("")
+            .map_err(Error::io(self.xdg_dirs.get_data_home()))
+    }
+
+    /// all theme dirs with their origin, in lookup order
+    pub fn sources(&self) -> Vec<(PathBuf, Origin)> {
+        let mut sources = vec![(self.xdg_dirs.get_data_home(), Origin::User)];
+        sources.extend(self.extra_dirs.iter().map(|dir| {
+            (
+                dir.clone(),
+                Origin::Extra {
+                    dir: dir.clone(),
+                    writable: is_writable_dir(dir),
+                },
+            )
+        }));
+        sources.extend(
+            self.xdg_dirs
+                .get_data_dirs()
+                .into_iter()
+                .map(|dir| (dir, Origin::System)),
+        );
+        sources
+    }
+
+    /// all theme dirs, in lookup order
+    pub fn dirs(&self) -> Vec<PathBuf> {
+        self.sources().into_iter().map(|(dir, _)| dir).collect()
+    }
+
+    /// the files containing a theme with `id`, in lookup order
+    fn find(&self, id: &str) -> Vec<(PathBuf, Origin)> {
+        if !is_valid_id(id) {
+            return Vec::new();
+        }
+        self.sources()
+            .into_iter()
+            .map(|(dir, origin)| (dir.join(format!("{id}.ron")), origin))
+            .filter(|(p, _)| p.is_file())
+            .collect()
+    }
+
+    /// the file the theme with `id` is loaded from
+    pub fn path(&self, id: &str) -> Option<PathBuf> {
+        self.find(id).into_iter().next().map(|(p, _)| p)
+    }
+
+    /// write `theme` to `p`, keeping the version it overwrites in the history
+    fn write_at(&self, p: &Path, theme: &Theme) -> Result<()> {
+        let ron = theme.to_ron()?;
+        match (std::fs::read_to_string(p), &self.history) {
+            (Ok(old), Some(history)) if old != ron => {
+                if let Err(err) = history.record(&theme.id, &old) {
+                    log::warn!("Failed to keep a revision of {}: {}", theme.id, err);
+                }
+            }
+            _ => {}
+        }
+        atomic::write(&p, ron)
+    }
+}
+
+impl ThemeStore for XdgThemeStore {
+    fn list(&self) -> Result<Vec<String>> {
+        let mut ids: Vec<String> = self
+            .dirs()
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|p| p.extension().map(|e| e == "ron").unwrap_or_default())
+            .filter_map(|p| Some(p.file_stem()?.to_string_lossy().into_owned()))
+            .filter(|id| is_valid_id(id))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    fn origin(&self, id: &str) -> Option<Origin> {
+        self.find(id).into_iter().next().map(|(_, origin)| origin)
+    }
+
+    fn get(&self, id: &str) -> Result<Theme> {
+        match self.path(id) {
+            Some(p) => Theme::load(&p),
+            None => Err(Error::ThemeNotFound {
+                name: id.to_string(),
+            }),
+        }
+    }
+
+    fn write(&mut self, theme: &Theme) -> Result<()> {
+        if !is_valid_id(&theme.id) {
+            return Err(Error::InvalidId {
+                id: theme.id.clone(),
+            });
+        }
+        let ron_name = format!("{}.ron", theme.id);
+        let p = match self.origin(&theme.id) {
+            Some(Origin::Extra {
+                dir,
+                writable: true,
+            }) => dir.join(ron_name),
+            _ => self
+                .xdg_dirs
+                .place_data_file(&ron_name)
+                .map_err(Error::io(self.xdg_dirs.get_data_home().join(&ron_name)))?,
+        };
+        self.write_at(&p, theme)
+    }
+
+    fn delete(&mut self, id: &str) -> Result<()> {
+        match self.find(id).into_iter().next() {
+            Some((p, origin)) if origin.is_writable() => {
+                std::fs::remove_file(&p).map_err(Error::io(&p))
+            }
+            Some(_) => Err(Error::ReadOnly { id: id.to_string() }),
+            None => Err(Error::ThemeNotFound {
+                name: id.to_string(),
+            }),
+        }
+    }
+
+    fn shadows(&self, id: &str) -> bool {
+        let found = self.find(id);
+        found.len() > 1 && found[0].1 == Origin::User
+    }
+
+    /// themes in writable extra dirs stay there, e.g. in a shared checkout of team themes
+    fn rename(&mut self, id: &str, new_id: &str) -> Result<()> {
+        let dir = match check_rename(self, id, new_id)? {
+            Origin::Extra { dir, .. } => dir,
+            _ => {
+                let mut theme = self.get(id)?;
+                theme.id = new_id.to_string();
+                self.put(&mut theme)?;
+                return self.delete(id);
+            }
+        };
+        let mut theme = self.get(id)?;
+        if theme.version > THEME_VERSION {
+            return Err(Error::NewerTheme {
+                id: theme.id,
+                version: theme.version,
+            });
+        }
+        theme.id = new_id.to_string();
+        self.write_at(&dir.join(format!("{new_id}.ron")), &theme)?;
+        self.delete(id)
+    }
+}
+
+/// Themes kept in memory, for tests
+#[derive(Debug, Default, Clone)]
+pub struct MemoryThemeStore {
+    user: BTreeMap<String, Theme>,
+    system: BTreeMap<String, Theme>,
+}
+
+impl MemoryThemeStore {
+    /// add a read-only system theme, using its id or a slug of its name
+    pub fn add_system(&mut self, mut theme: Theme) {
+        if theme.id.is_empty() {
+            theme.id = slugify(&theme.name);
+        }
+        self.system.insert(theme.id.clone(), theme);
+    }
+}
+
+impl ThemeStore for MemoryThemeStore {
+    fn list(&self) -> Result<Vec<String>> {
+        let mut ids: Vec<String> = self
+            .user
+            .keys()
+            .chain(self.system.keys())
+            .cloned()
+            .collect();
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    fn origin(&self, id: &str) -> Option<Origin> {
+        if self.user.contains_key(id) {
+            Some(Origin::User)
+        } else if self.system.contains_key(id) {
+            Some(Origin::System)
+        } else {
+            None
+        }
+    }
+
+    fn get(&self, id: &str) -> Result<Theme> {
+        match self.user.get(id).or_else(|| self.system.get(id)) {
+            Some(theme) => Ok(theme.clone()),
+            None => Err(Error::ThemeNotFound {
+                name: id.to_string(),
+            }),
+        }
+    }
+
+    fn write(&mut self, theme: &Theme) -> Result<()> {
+        if !is_valid_id(&theme.id) {
+            return Err(Error::InvalidId {
+                id: theme.id.clone(),
+            });
+        }
+        self.user.insert(theme.id.clone(), theme.clone());
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &str) -> Result<()> {
+        match self.origin(id) {
+            Some(Origin::User) => {
+                self.user.remove(id);
+                Ok(())
+            }
+            Some(_) => Err(Error::ReadOnly { id: id.to_string() }),
+            None => Err(Error::ThemeNotFound {
+                name: id.to_string(),
+            }),
+        }
+    }
+
+    fn shadows(&self, id: &str) -> bool {
+        self.user.contains_key(id) && self.system.contains_key(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unique_id, MemoryThemeStore, Origin, ThemeStore, XdgThemeStore};
+    use crate::{colors::ColorOverrides, error::Error, test_util::TempDir, theme::Theme};
+
+    fn theme(name: &str) -> Theme {
+        let mut theme = Theme::from(ColorOverrides::dark_default());
+        theme.set_name(name);
+        theme
+    }
+
+    #[test]
+    fn unique_ids() {
+        let taken = ["nord", "nord-2"];
+        assert_eq!(unique_id("Nord", |id| taken.contains(&id)), "nord-3");
+        assert_eq!(
+            unique_id("Nord Light", |id| taken.contains(&id)),
+            "nord-light"
+        );
+    }
+
+    #[test]
+    fn put_get_delete() {
+        let mut store = MemoryThemeStore::default();
+        store.add_system(theme("Nord"));
+
+        let mut user = theme("Nord");
+        store.put(&mut user).unwrap();
+        assert_eq!(user.id, "nord-2");
+        assert_eq!(store.list().unwrap(), vec!["nord", "nord-2"]);
+        assert_eq!(store.origin("nord"), Some(Origin::System));
+        assert_eq!(store.origin("nord-2"), Some(Origin::User));
+        assert_eq!(store.get("nord-2").unwrap(), user);
+
+        // existing themes keep their id
+        user.set_name("Nord Tweaked");
+        store.put(&mut user).unwrap();
+        assert_eq!(user.id, "nord-2");
+
+        assert!(matches!(store.delete("nord"), Err(Error::ReadOnly { .. })));
+        store.delete("nord-2").unwrap();
+        assert_eq!(store.origin("nord-2"), None);
+    }
+
+    #[test]
+    fn shadowing() {
+        let mut store = MemoryThemeStore::default();
+        store.add_system(theme("Nord"));
+        let mut user = theme("My Nord");
+        user.id = "nord".into();
+        store.put(&mut user).unwrap();
+
+        assert_eq!(store.list().unwrap(), vec!["nord"]);
+        assert_eq!(store.origin("nord"), Some(Origin::User));
+        assert_eq!(store.get("nord").unwrap().name, "My Nord");
+        assert!(store.shadows("nord"));
+
+        // the system theme is visible again without the user theme
+        assert_eq!(store.revert("nord").unwrap().name, "Nord");
+        assert_eq!(store.origin("nord"), Some(Origin::System));
+        assert!(!store.shadows("nord"));
+        assert!(matches!(
+            store.revert("nord"),
+            Err(Error::NoOtherVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn rename_and_duplicate() {
+        let mut store = MemoryThemeStore::default();
+        store.add_system(theme("Nord"));
+
+        let copy = store.duplicate("nord", "Nord Copy").unwrap();
+        assert_eq!(copy.id, "nord-copy");
+        assert_eq!(copy.name, "Nord Copy");
+        assert_eq!(store.origin("nord-copy"), Some(Origin::User));
+
+        assert!(matches!(
+            store.rename("nord", "polar"),
+            Err(Error::ReadOnly { .. })
+        ));
+        assert!(matches!(
+            store.rename("nord-copy", "nord"),
+            Err(Error::ThemeExists { .. })
+        ));
+        assert!(matches!(
+            store.rename("nord-copy", "../polar"),
+            Err(Error::InvalidId { .. })
+        ));
+        store.rename("nord-copy", "polar").unwrap();
+        assert_eq!(store.list().unwrap(), vec!["nord", "polar"]);
+        assert_eq!(store.get("polar").unwrap().id, "polar");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extra_dirs() {
+        let root = TempDir::new("extra");
+        let (team, shared) = (root.join("team"), root.join("shared"));
+        std::fs::create_dir_all(&team).unwrap();
+        std::fs::create_dir_all(&shared).unwrap();
+        let id = format!("team-theme-{}", std::process::id());
+        let mut team_theme = theme("Team");
+        team_theme.id = id.clone();
+        std::fs::write(team.join(format!("{id}.ron")), team_theme.to_ron().unwrap()).unwrap();
+        std::fs::write(
+            shared.join(format!("{id}.ron")),
+            team_theme.to_ron().unwrap(),
+        )
+        .unwrap();
+        let mut permissions = std::fs::metadata(&shared).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&shared, permissions).unwrap();
+
+        let mut store = XdgThemeStore::with_extra_dirs(vec![team.clone(), shared.clone()]).unwrap();
+        assert!(store.list().unwrap().contains(&id));
+        assert_eq!(
+            store.origin(&id),
+            Some(Origin::Extra {
+                dir: team.clone(),
+                writable: true
+            })
+        );
+        assert!(!store.shadows(&id));
+
+        // writable extra dirs are changed in place
+        team_theme.set_name("Team Tweaked");
+        store.put(&mut team_theme).unwrap();
+        assert_eq!(
+            Theme::load(&team.join(format!("{id}.ron"))).unwrap().name,
+            "Team Tweaked"
+        );
+
+        // the read-only copy becomes visible, but can't be deleted
+        store.delete(&id).unwrap();
+        let is_root = unsafe { libc::geteuid() } == 0;
+        assert_eq!(
+            store.origin(&id),
+            Some(Origin::Extra {
+                dir: shared.clone(),
+                writable: is_root
+            })
+        );
+        // root may write anywhere
+        if !is_root {
+            assert!(store.delete(&id).is_err());
+        }
+
+        let mut permissions = std::fs::metadata(&shared).unwrap().permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        std::fs::set_permissions(&shared, permissions).unwrap();
+    }
+
+    #[test]
+    fn rename_in_extra_dir() {
+        let team = TempDir::new("rename");
+        let (id, new_id) = (
+            format!("team-theme-{}", std::process::id()),
+            format!("renamed-theme-{}", std::process::id()),
+        );
+        let mut team_theme = theme("Team");
+        team_theme.id = id.clone();
+        std::fs::write(team.join(format!("{id}.ron")), team_theme.to_ron().unwrap()).unwrap();
+
+        let mut store = XdgThemeStore::with_extra_dirs(vec![team.to_path_buf()]).unwrap();
+        store.rename(&id, &new_id).unwrap();
+        // the renamed theme stays in the extra dir instead of moving to the user dir
+        assert_eq!(
+            store.origin(&new_id),
+            Some(Origin::Extra {
+                dir: team.to_path_buf(),
+                writable: true
+            })
+        );
+        assert_eq!(
+            store.path(&new_id),
+            Some(team.join(format!("{new_id}.ron")))
+        );
+        assert_eq!(store.get(&new_id).unwrap().id, new_id);
+        assert_eq!(store.origin(&id), None);
+    }
+}
@@ -0,0 +1,36 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+//! Helpers shared by the tests of the modules
+
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+/// An empty dir for a test, removed when it is dropped, also if the test panics
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `name` must be unique among the tests, they run in parallel
+    pub(crate) fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("user-colors-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,497 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic,
+    colors::{ColorOverrides, Variant},
+    config::Config,
+    error::{Error, Result},
+    store::ThemeStore,
+};
+
+/// One of the variants a theme document can contain
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum VariantSlot {
+    #[default]
+    Light,
+    Dark,
+    HighContrastLight,
+    HighContrastDark,
+}
+
+impl VariantSlot {
+    pub const ALL: [VariantSlot; 4] = [
+        VariantSlot::Light,
+        VariantSlot::Dark,
+        VariantSlot::HighContrastLight,
+        VariantSlot::HighContrastDark,
+    ];
+
+    pub fn new(is_dark: bool, is_high_contrast: bool) -> Self {
+        match (is_dark, is_high_contrast) {
+            (false, false) => VariantSlot::Light,
+            (true, false) => VariantSlot::Dark,
+            (false, true) => VariantSlot::HighContrastLight,
+            (true, true) => VariantSlot::HighContrastDark,
+        }
+    }
+
+    pub fn is_dark(self) -> bool {
+        matches!(self, VariantSlot::Dark | VariantSlot::HighContrastDark)
+    }
+
+    pub fn is_high_contrast(self) -> bool {
+        matches!(
+            self,
+            VariantSlot::HighContrastLight | VariantSlot::HighContrastDark
+        )
+    }
+}
+
+/// Version of the theme schema written by this library
+///
+/// 0. a single `ColorOverrides`
+/// 1. a `Theme` with one set of overrides per variant
+/// 2. `Theme` with a version field
+/// 3. `Theme` with metadata
+pub const THEME_VERSION: u32 = 3;
+
+fn unversioned() -> u32 {
+    1
+}
+
+/// A named theme with up to one set of color overrides per variant
+///
+/// Missing fields take their default value and unknown fields are ignored,
+/// so themes written by newer versions of the library can still be loaded.
+#[derive(Debug, Deserialize, Serialize, Clone, Hash, PartialEq, Eq)]
+#[serde(default)]
+pub struct Theme {
+    /// schema version, see [`THEME_VERSION`]
+    #[serde(default = "unversioned")]
+    pub version: u32,
+    /// file stem of the theme, empty for themes which were never saved
+    #[serde(skip)]
+    pub id: String,
+    /// display name
+    pub name: String,
+    pub metadata: Metadata,
+    pub light: Option<ColorOverrides>,
+    pub dark: Option<ColorOverrides>,
+    pub high_contrast_light: Option<ColorOverrides>,
+    pub high_contrast_dark: Option<ColorOverrides>,
+}
+
+/// Information about who made a theme and what it is for
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Hash, PartialEq, Eq)]
+#[serde(default)]
+pub struct Metadata {
+    pub author: Option<String>,
+    pub description: Option<String>,
+    /// SPDX license identifier
+    pub license: Option<String>,
+    pub tags: Vec<String>,
+    /// creation time in seconds since the unix epoch
+    pub created: Option<u64>,
+    /// last modification time in seconds since the unix epoch
+    pub modified: Option<u64>,
+    /// where the theme was downloaded from
+    pub origin: Option<String>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            version: THEME_VERSION,
+            id: Default::default(),
+            name: Default::default(),
+            metadata: Default::default(),
+            light: Default::default(),
+            dark: Default::default(),
+            high_contrast_light: Default::default(),
+            high_contrast_dark: Default::default(),
+        }
+    }
+}
+
+impl Theme {
+    /// the overrides stored for `slot`
+    pub fn get(&self, slot: VariantSlot) -> Option<&ColorOverrides> {
+        match slot {
+            VariantSlot::Light => self.light.as_ref(),
+            VariantSlot::Dark => self.dark.as_ref(),
+            VariantSlot::HighContrastLight => self.high_contrast_light.as_ref(),
+            VariantSlot::HighContrastDark => self.high_contrast_dark.as_ref(),
+        }
+    }
+
+    /// replace the overrides stored for `slot`
+    pub fn set(&mut self, slot: VariantSlot, overrides: Option<ColorOverrides>) {
+        let overrides = overrides.map(|mut o| {
+            o.name = self.name.clone();
+            o
+        });
+        match slot {
+            VariantSlot::Light => self.light = overrides,
+            VariantSlot::Dark => self.dark = overrides,
+            VariantSlot::HighContrastLight => self.high_contrast_light = overrides,
+            VariantSlot::HighContrastDark => self.high_contrast_dark = overrides,
+        }
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+        for slot in VariantSlot::ALL {
+            if let Some(mut o) = self.get(slot).cloned() {
+                o.name = self.name.clone();
+                self.set(slot, Some(o));
+            }
+        }
+    }
+
+    /// update the modification time, setting the creation time of new themes
+    pub fn touch(&mut self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.metadata.created.get_or_insert(now);
+        self.metadata.modified = Some(now);
+    }
+
+    /// whether the theme is tagged with `tag`, ignoring case
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.metadata
+            .tags
+            .iter()
+            .any(|t| t.trim().eq_ignore_ascii_case(tag.trim()))
+    }
+
+    /// the slots which contain overrides
+    pub fn slots(&self) -> impl Iterator<Item = VariantSlot> + '_ {
+        VariantSlot::ALL
+            .into_iter()
+            .filter(|slot| self.get(*slot).is_some())
+    }
+
+    /// whether the theme has overrides meant for `variant`
+    pub fn provides(&self, variant: Variant) -> bool {
+        match variant {
+            Variant::Light => self.light.is_some(),
+            Variant::Dark => self.dark.is_some(),
+        }
+    }
+
+    /// the overrides to use for the given mode
+    ///
+    /// Falls back to the other variant if the theme only has one, and to the algorithmic
+    /// high contrast transform if the theme has no high contrast variant.
+    pub fn resolve(&self, is_dark: bool, is_high_contrast: bool) -> Option<ColorOverrides> {
+        if is_high_contrast {
+            if let Some(o) = self.get(VariantSlot::new(is_dark, true)) {
+                return Some(o.clone());
+            }
+            return self
+                .resolve(is_dark, false)
+                .map(ColorOverrides::to_high_contrast);
+        }
+        self.get(VariantSlot::new(is_dark, false))
+            .or_else(|| self.get(VariantSlot::new(!is_dark, false)))
+            .cloned()
+    }
+
+    /// parse a theme written with any version of the schema
+    pub fn from_ron(s: &str) -> Result<Self> {
+        Ok(Self::from_ron_versioned(s)?.0)
+    }
+
+    /// parse a theme, returning the schema version it was written with
+    pub fn from_ron_versioned(s: &str) -> Result<(Self, u32)> {
+        let (mut theme, version) = match ron::from_str::<Theme>(s) {
+            Ok(t) if t.slots().next().is_some() => {
+                let version = t.version;
+                (t, version)
+            }
+            // version 0 files have the fields of a single variant at the top level
+            _ => (
+                Theme::from(
+                    ron::from_str::<ColorOverrides>(s).map_err(|err| Error::parse(None, err))?,
+                ),
+                0,
+            ),
+        };
+        let name = theme.name.clone();
+        theme.set_name(&name);
+        theme.version = version.max(THEME_VERSION);
+        Ok((theme, version))
+    }
+
+    pub fn to_ron(&self) -> Result<String> {
+        Ok(ron::ser::to_string(self)?)
+    }
+
+    /// rewrite a theme file with the current schema version
+    ///
+    /// Returns the version the file was upgraded from, if it was outdated. Files of a newer
+    /// version are left unchanged and reported as [`Error::NewerVersion`].
+    pub fn upgrade_in_place(p: &dyn AsRef<Path>) -> Result<Option<u32>> {
+        let p = p.as_ref();
+        let s = std::fs::read_to_string(p).map_err(Error::io(p))?;
+        let (theme, version) = Self::from_ron_versioned(&s).map_err(|err| err.in_file(p))?;
+        if version > THEME_VERSION {
+            return Err(Error::NewerVersion {
+                path: p.to_path_buf(),
+                version,
+            });
+        }
+        if version == THEME_VERSION {
+            return Ok(None);
+        }
+        atomic::write(&p, theme.to_ron()?)?;
+        Ok(Some(version))
+    }
+
+    /// save the theme to the user theme dir, see [`ThemeStore::put`]
+    pub fn save(&mut self, config: &Config) -> Result<()> {
+        config.theme_store()?.put(self)
+    }
+
+    /// copy the theme file at `p` to the user theme dir, as a new theme
+    pub fn import(p: &dyn AsRef<Path>, config: &Config) -> Result<Self> {
+        let mut theme = Self::load(p)?;
+        if theme.name.is_empty() {
+            let name = theme.id.clone();
+            theme.set_name(&name);
+        }
+        theme.id = String::new();
+        config.theme_store()?.put(&mut theme)?;
+        Ok(theme)
+    }
+
+    /// the theme with `id` in the theme dirs of `config`
+    pub fn load_from_id(config: &Config, id: &str) -> Result<Self> {
+        config.theme_store()?.get(id)
+    }
+
+    pub fn load(p: &dyn AsRef<Path>) -> Result<Self> {
+        let p = p.as_ref();
+        let s = std::fs::read_to_string(p).map_err(Error::io(p))?;
+        let mut theme = Self::from_ron(&s).map_err(|err| err.in_file(p))?;
+        theme.id = p
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(theme)
+    }
+}
+
+/// derive a theme id from a display name
+///
+/// The id is lower case and only contains alphanumeric characters and dashes,
+/// so it is safe to use as a file name.
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "theme".into()
+    } else {
+        slug.into()
+    }
+}
+
+/// whether `id` names a file inside the theme dir
+///
+/// Themes saved before ids were introduced use their display name as id,
+/// so anything but path separators and relative paths is accepted.
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && !id.starts_with('.') && !id.contains(['/', '\\', '\0'])
+}
+
+impl From<ColorOverrides> for Theme {
+    /// a theme with a single variant, chosen by classifying the overrides
+    fn from(overrides: ColorOverrides) -> Self {
+        let mut theme = Theme {
+            name: overrides.name.clone(),
+            ..Default::default()
+        };
+        let is_dark = overrides.variant().map(Variant::is_dark).unwrap_or(false);
+        theme.set(VariantSlot::new(is_dark, false), Some(overrides));
+        theme
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_valid_id, slugify, Theme, VariantSlot, THEME_VERSION};
+    use crate::{
+        colors::{ColorOverrides, Variant},
+        error::Error,
+        store::{MemoryThemeStore, ThemeStore},
+        test_util::TempDir,
+    };
+
+    #[test]
+    fn single_variant_round_trip() {
+        let dark = ColorOverrides::dark_default();
+        let theme = Theme::from_ron(&ron::ser::to_string(&dark).unwrap()).unwrap();
+        assert_eq!(theme.name, dark.name);
+        assert_eq!(theme.slots().collect::<Vec<_>>(), vec![VariantSlot::Dark]);
+        assert_eq!(theme.resolve(false, false), Some(dark.clone()));
+
+        let (saved, version) = Theme::from_ron_versioned(&theme.to_ron().unwrap()).unwrap();
+        assert_eq!(version, THEME_VERSION);
+        assert_eq!(saved, theme);
+    }
+
+    #[test]
+    fn paired_round_trip() {
+        let mut theme = Theme {
+            name: "paired".into(),
+            ..Default::default()
+        };
+        theme.set(VariantSlot::Light, Some(ColorOverrides::light_default()));
+        theme.set(VariantSlot::Dark, Some(ColorOverrides::dark_default()));
+
+        let loaded = Theme::from_ron(&theme.to_ron().unwrap()).unwrap();
+        assert_eq!(loaded, theme);
+        assert_eq!(loaded.resolve(false, false).unwrap().name, "paired");
+        assert_eq!(
+            loaded.resolve(true, false).unwrap().window_bg_color,
+            ColorOverrides::dark_default().window_bg_color
+        );
+        assert_eq!(
+            loaded.resolve(true, true).unwrap().window_bg_color,
+            ColorOverrides::dark_default().window_bg_color
+        );
+    }
+
+    #[test]
+    fn version_0_fixture() {
+        let (theme, version) =
+            Theme::from_ron_versioned(include_str!("../tests/fixtures/v0_single.ron")).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(theme.version, THEME_VERSION);
+        assert_eq!(theme.name, "midnight");
+        let dark = theme.get(VariantSlot::Dark).unwrap();
+        assert_eq!(dark.name, "midnight");
+        assert_eq!(dark.variant, None);
+        assert_eq!(dark.variant(), Some(Variant::Dark));
+        assert_eq!(dark.accent_bg_color.as_deref(), Some("#9141ac"));
+    }
+
+    #[test]
+    fn version_1_fixture() {
+        let (theme, version) =
+            Theme::from_ron_versioned(include_str!("../tests/fixtures/v1_paired.ron")).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(theme.name, "dusk");
+        assert!(theme.provides(Variant::Light) && theme.provides(Variant::Dark));
+        assert_eq!(
+            theme.get(VariantSlot::Dark).unwrap().window_bg_color,
+            ColorOverrides::dark_default().window_bg_color
+        );
+    }
+
+    #[test]
+    fn unknown_and_missing_fields() {
+        let theme = Theme::from_ron(
+            "(version: 7, name: \"future\", light: Some((window_bg_color: Some(\"#fafafa\"), corner_radius: 8)), tags: [\"new\"])",
+        )
+        .unwrap();
+        assert_eq!(theme.version, 7);
+        assert_eq!(theme.slots().collect::<Vec<_>>(), vec![VariantSlot::Light]);
+        assert_eq!(theme.get(VariantSlot::Light).unwrap().name, "future");
+    }
+
+    #[test]
+    fn upgrade_in_place() {
+        let dir = TempDir::new("upgrade");
+        let p = dir.join("midnight.ron");
+        std::fs::write(&p, include_str!("../tests/fixtures/v0_single.ron")).unwrap();
+
+        assert_eq!(Theme::upgrade_in_place(&p).unwrap(), Some(0));
+        let upgraded = std::fs::read_to_string(&p).unwrap();
+        let (theme, version) = Theme::from_ron_versioned(&upgraded).unwrap();
+        assert_eq!(version, THEME_VERSION);
+        assert_eq!(theme.name, "midnight");
+        assert_eq!(Theme::upgrade_in_place(&p).unwrap(), None);
+
+        // fields of newer versions would be lost, so those files are left alone
+        let newer = format!(
+            "(version: {}, name: \"future\", light: Some((window_bg_color: Some(\"#fafafa\"), corner_radius: 8)))",
+            THEME_VERSION + 1
+        );
+        std::fs::write(&p, &newer).unwrap();
+        assert!(matches!(
+            Theme::upgrade_in_place(&p),
+            Err(Error::NewerVersion { version, .. }) if version == THEME_VERSION + 1
+        ));
+        assert_eq!(std::fs::read_to_string(&p).unwrap(), newer);
+        let mut store = MemoryThemeStore::default();
+        assert!(matches!(
+            store.put(&mut Theme::load(&p).unwrap()),
+            Err(Error::NewerTheme { .. })
+        ));
+
+        // errors point at the broken line of the file
+        std::fs::write(&p, "(\n    name: \"midnight\",\n    light: Some(\n").unwrap();
+        match Theme::load(&p) {
+            Err(Error::Parse { path, line, .. }) => {
+                assert_eq!(path.as_deref(), Some(p.as_path()));
+                assert_eq!(line, 4);
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+        assert!(matches!(
+            Theme::load(&dir.join("missing.ron")),
+            Err(Error::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn metadata() {
+        let mut theme = Theme::from(ColorOverrides::dark_default());
+        assert_eq!(theme.metadata, Default::default());
+
+        theme.metadata.author = Some("Ada".into());
+        theme.metadata.tags = vec!["Solarized".into(), "warm ".into()];
+        theme.touch();
+        let created = theme.metadata.created.unwrap();
+        assert_eq!(theme.metadata.modified, Some(created));
+        theme.touch();
+        assert_eq!(theme.metadata.created, Some(created));
+
+        let loaded = Theme::from_ron(&theme.to_ron().unwrap()).unwrap();
+        assert_eq!(loaded.metadata, theme.metadata);
+        assert!(loaded.has_tag("solarized") && loaded.has_tag("warm"));
+        assert!(!loaded.has_tag("cold"));
+    }
+
+    #[test]
+    fn ids() {
+        assert_eq!(slugify("Solarized Dark"), "solarized-dark");
+        assert_eq!(slugify("  ../../etc/passwd "), "etc-passwd");
+        assert_eq!(slugify("Ünïcode—Theme!"), "ünïcode-theme");
+        assert_eq!(slugify("/.."), "theme");
+
+        assert!(is_valid_id("solarized-dark"));
+        assert!(is_valid_id("My Old Theme"));
+        assert!(!is_valid_id(""));
+        assert!(!is_valid_id(".."));
+        assert!(!is_valid_id("../config"));
+        assert!(!is_valid_id("a/b"));
+    }
+}
@@ -6,9 +6,21 @@ current-theme = Current theme:
 apply-to-all-apps = Color all applications (Restart non-cosmic apps to apply changes)
 current-light-theme = Current light theme:
 current-dark-theme = Current dark theme:
+current-high-contrast-light-theme = Current high contrast light theme:
+current-high-contrast-dark-theme = Current high contrast dark theme:
 dark-light-switch = Enable dark / light switching
 set-dark-switch = Dark Mode
 set-high-contrast-switch = High Contrast Mode
+light-theme = Light theme
+dark-theme = Dark theme
+light-and-dark-theme = Light and dark theme
+dark-theme-in-light-slot = This looks like a dark theme, but it will be used while dark mode is off.
+light-theme-in-dark-slot = This looks like a light theme, but it will be used while dark mode is on.
+theme-variant = Variant:
+light-variant = Light
+dark-variant = Dark
+high-contrast-light-variant = High Contrast Light
+high-contrast-dark-variant = High Contrast Dark
 
 accent-Colors = Accent Colors
 accent-background-color = Accent Background Color
@@ -56,4 +68,68 @@ popover-background-color = Popover Background Color
 popover-foreground-color = Popover Foreground Color
 miscellaneous-colors = Miscellaneous Colors
 scrollbar-outline-color = Scrollbar Outline Color
-shade-color = Shade Color
\ No newline at end of file
+shade-color = Shade Color
+clear = Clear
+
+about-theme = About this theme
+theme-author = Author
+theme-description = Description
+theme-license = License
+theme-tags = Tags
+theme-tags-placeholder = Comma separated, e.g. warm, solarized
+theme-origin = Origin URL
+theme-variants = Variants
+theme-created = Created
+theme-modified = Modified
+all-tags = All tags
+filter-by-tag = Only show themes with this tag
+
+save-theme-copy = Save your version
+revert-theme = Revert to original version
+system-theme-read-only = This theme is provided by the system and can't be changed. Saving keeps your version in your themes, the original can be restored.
+theme-dir-read-only = This theme is in the read-only folder { $dir } and can't be changed. Saving keeps your version in your themes, the original can be restored.
+system-theme-source = System
+read-only-theme-dir = read-only
+gtk-css-position = Where the colors are imported in gtk.css. Rules further down override earlier ones.
+gtk-css-top = Top of gtk.css
+gtk-css-bottom = Bottom of gtk.css
+reset-all = Remove all customizations
+reset-all-confirm = Remove all customizations?
+reset-all-details = gtk.css is restored to how it was before the colors were first applied, cosmic.css is deleted and the editor settings are reset. Your themes are kept.
+keep-colors = Keep these colors?
+keep-colors-countdown = The previous colors are restored in { $seconds } seconds.
+keep-colors-button = Keep colors
+revert-colors = Revert
+revision-history = Revision history
+revision-count = { $count ->
+    [0] No earlier versions
+    [one] One earlier version
+   *[other] { $count } earlier versions
+}
+restore-revision = Restore
+revision-unchanged = Same colors as the current version
+unset-color = unset
+active-theme-missing = The active theme { $name } no longer exists. Select another theme to apply.
+theme-problems = This theme has problems
+lint-error = Error
+lint-warning = Warning
+lint-info = Note
+save-anyway = Save anyway
+import-anyway = Import anyway
+import-failed = The theme could not be imported. { $error }
+colors-rejected = These colors were left out because they are not valid colors: { $keys }
+custom-css = Custom CSS
+custom-css-details = Added after the colors of this variant, for changes no color covers. Other files and images can't be loaded.
+custom-css-parse-error = Line { $line }: { $error }
+apply-custom-css = Include the custom CSS of themes
+generated-css = Generated CSS
+generated-css-details = The CSS applied for this variant. Edit the @define-color lines or paste them from another stylesheet to change the colors, other lines become custom CSS.
+copy-css = Copy to clipboard
+save-failed = The theme could not be saved. { $error }
+revert-failed = The original theme could not be restored. { $error }
+import-theme = Import theme
+import-theme-title = Import Theme
+reset-all-failed = Failed to remove all customizations. { $error }
+restore-colors-failed = Failed to restore the previous colors. { $error }
+apply-failed = The colors could not be applied. { $error }
+restore-revision-failed = Failed to restore the revision. { $error }
\ No newline at end of file
@@ -1,24 +1,63 @@
 // SPDX-License-Identifier: MPL-2.0-only
 
-use crate::components::theme_import_button::ThemeImportButton;
-use gtk4::{gio::Settings, glib, subclass::prelude::*, Box, Button, CssProvider, Entry, Switch};
+use crate::components::{
+    theme_about::ThemeAbout, theme_history::ThemeHistory, theme_import_button::ThemeImportButton,
+};
+use gtk4::{
+    gio::Settings, glib, subclass::prelude::*, Box, Button, ColorButton, CssProvider, DropDown,
+    Entry, Label, Switch, TextBuffer, TextView,
+};
 use once_cell::sync::OnceCell;
-use std::{cell::RefCell, rc::Rc};
-use user_colors::{colors::ColorOverrides, config::Config};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+use user_colors::{
+    colors::ColorOverrides,
+    config::Config,
+    theme::{Theme, VariantSlot},
+};
 
 // Object holding the state
 #[derive(Default)]
 pub struct ColorOverridesEditor {
     pub name: Rc<OnceCell<Entry>>,
     pub save: Rc<OnceCell<Button>>,
+    /// shown for user themes which shadow a system theme
+    pub revert: OnceCell<Button>,
+    /// shown for read-only system themes
+    pub read_only: OnceCell<Label>,
     pub file_button: OnceCell<ThemeImportButton>,
+    /// the variant of `document` being edited
     pub theme: Rc<RefCell<ColorOverrides>>,
+    pub document: Rc<RefCell<Theme>>,
+    pub about: OnceCell<ThemeAbout>,
+    pub history: OnceCell<ThemeHistory>,
+    pub slot: Cell<VariantSlot>,
+    pub variant_dropdown: Rc<OnceCell<DropDown>>,
+    /// set while the variant dropdown follows a newly opened theme
+    pub switching: Cell<bool>,
     pub config: Rc<RefCell<Config>>,
     pub css_provider: Rc<OnceCell<CssProvider>>,
     pub color_editor: Rc<OnceCell<Box>>,
+    /// the color buttons of the variant by key
+    pub color_buttons: RefCell<HashMap<String, ColorButton>>,
+    /// the custom css of the variant
+    pub custom_css: RefCell<Option<TextBuffer>>,
+    /// the css generated from the variant, edits are parsed back into it
+    pub generated_css: OnceCell<TextView>,
+    pub generated_css_error: OnceCell<Label>,
+    /// set while widgets follow an edit of the generated css, which must not be stored again
+    pub syncing: Cell<bool>,
     pub dark_settings: Rc<OnceCell<Settings>>,
     pub high_contrast_settings: Rc<OnceCell<Settings>>,
     pub dark_light_switch: Rc<OnceCell<Switch>>,
+    /// set while the config widgets follow a restored config, which must not be saved again
+    pub restoring: Cell<bool>,
+    /// widgets for the options of the config mode
+    pub config_section: OnceCell<Box>,
+    pub reset: OnceCell<Button>,
 }
 
 // The central trait for subclassing a GObject
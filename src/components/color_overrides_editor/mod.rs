@@ -2,11 +2,14 @@
 
 use crate::{
     components::{
+        theme_about::ThemeAbout,
         theme_dropdown::{ThemeDropdown, Watch},
+        theme_history::ThemeHistory,
         theme_import_button::ThemeImportButton,
     },
     fl,
-    util::{hex_from_rgba, SRGBA},
+    theme_model::{self, theme_id},
+    util::{confirm_diagnostics, hex_from_rgba, SRGBA},
 };
 
 use adw::{traits::ExpanderRowExt, ExpanderRow};
@@ -17,14 +20,30 @@ use gtk4::{
     glib::{self, closure_local},
     prelude::*,
     subclass::prelude::*,
-    Align, Box, Button, ColorButton, CssProvider, Entry, Label, MessageDialog, Orientation,
-    ScrolledWindow, Switch, Window,
+    Align, Box, Button, ColorButton, CssProvider, DropDown, Entry, Expander, Label, MessageDialog,
+    Orientation, ScrolledWindow, StringList, Switch, TextView, Window, WrapMode,
 };
 use relm4_macros::view;
-use std::fmt::Display;
-use user_colors::{colors::ColorOverrides, config::Config};
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Display,
+    rc::Rc,
+};
+use user_colors::{
+    colors::{check_custom_css, ColorOverrides, Variant},
+    config::{AppliedState, Config, Mode},
+    error::Error,
+    gtk_css::BlockPosition,
+    history::History,
+    lint::{lint, Diagnostic},
+    store::{Origin, ThemeStore},
+    theme::{Theme, VariantSlot},
+};
 mod imp;
 
+/// seconds until newly applied colors are reverted, unless the user keeps them
+const CONFIRM_SECONDS: u32 = 15;
+
 glib::wrapper! {
     pub struct ColorOverridesEditor(ObjectSubclass<imp::ColorOverridesEditor>)
         @extends gtk4::Box, gtk4::Widget,
@@ -66,6 +85,36 @@ impl ColorOverridesEditor {
                     set_width_request: 160,
                 },
 
+                append: read_only_label = &Label {
+                    set_text: &fl!("system-theme-read-only"),
+                    set_xalign: 0.0,
+                    set_wrap: true,
+                    add_css_class: "dim-label",
+                    set_visible: false,
+                },
+
+                append: about = &ThemeAbout::new(imp.document.clone()),
+
+                append: history = &ThemeHistory::new(imp.document.clone()),
+
+                append = &Box {
+                    set_orientation: Orientation::Horizontal,
+                    set_spacing: 4,
+                    set_margin_top: 4,
+                    set_margin_bottom: 4,
+                    set_margin_start: 4,
+                    set_margin_end: 4,
+                    append = &Label {
+                        set_text: &fl!("theme-variant"),
+                    },
+                    append: variant_dropdown = &DropDown::from_strings(&[
+                        &fl!("light-variant"),
+                        &fl!("dark-variant"),
+                        &fl!("high-contrast-light-variant"),
+                        &fl!("high-contrast-dark-variant"),
+                    ]),
+                },
+
                 append: color_box = &Box {
                     set_orientation: Orientation::Vertical,
                     set_spacing: 4,
@@ -75,6 +124,14 @@ impl ColorOverridesEditor {
                     set_margin_end: 4,
                 },
 
+                append: generated_css_expander = &Expander {
+                    set_label: Some(&fl!("generated-css")),
+                    set_margin_top: 4,
+                    set_margin_bottom: 4,
+                    set_margin_start: 4,
+                    set_margin_end: 4,
+                },
+
 
                 // TODO add the rest label for each section
 
@@ -105,6 +162,15 @@ impl ColorOverridesEditor {
                         add_css_class: "suggested-action",
                         set_label: &fl!("save-theme")
                     },
+
+                    append: revert_button = &Button {
+                        set_margin_top: 4,
+                        set_margin_bottom: 4,
+                        set_margin_start: 4,
+                        set_margin_end: 4,
+                        set_label: &fl!("revert-theme"),
+                        set_visible: false,
+                    },
                 },
                 append = &Box {
                     set_orientation: Orientation::Horizontal,
@@ -122,24 +188,29 @@ impl ColorOverridesEditor {
                 append: config_section = &Box {
                     set_orientation: Orientation::Vertical,
                 },
+
+                append: reset_button = &Button {
+                    set_margin_top: 4,
+                    set_margin_bottom: 4,
+                    set_margin_start: 4,
+                    set_margin_end: 4,
+                    set_halign: Align::Start,
+                    add_css_class: "destructive-action",
+                    set_label: &fl!("reset-all"),
+                },
             }
         };
 
         // if no valid config exists, create one
-        let mut config = match Config::load() {
-            Ok(c) => c,
-            Err(_) => {
-                let c = Config::default();
-                c.save().unwrap();
-                c
-            }
-        };
+        let mut config = Config::load()
+            .or_else(|_| Config::update(|_| {}))
+            .unwrap_or_default();
         // init state of switch
-        match config {
-            Config::DarkLight { .. } => {
+        match config.mode {
+            Mode::DarkLight { .. } => {
                 dark_light_switch.set_state(true);
             }
-            Config::Static { .. } => {
+            Mode::Static { .. } => {
                 dark_light_switch.set_state(false);
             }
         }
@@ -154,35 +225,33 @@ impl ColorOverridesEditor {
                 Some("color-scheme"),
                 glib::clone!(@weak self_ => move |settings, _| {
                     let dark = settings.string("color-scheme").as_str() != "prefer-light";
-                    let mut config: Config = self_.imp().config.borrow().clone();
-                    if match config {
-                        Config::DarkLight { ref mut is_dark, .. } if *is_dark != dark => {
+                    if !matches!(self_.imp().config.borrow().mode, Mode::DarkLight { is_dark, .. } if is_dark != dark) {
+                        return;
+                    }
+                    let config = self_.update_config(|c| {
+                        if let Mode::DarkLight { is_dark, .. } = &mut c.mode {
                             *is_dark = dark;
-                            true
-                        },
-                        _ => false
-                    } {
-                        if let Some(name) = config.active_name() {
-                            if let Ok(palette) = ColorOverrides::load_from_name(&name) {
-                                self_.imp().theme.replace(palette);
-                            }
-                            let _ = config.apply_gtk4();
                         }
-                        let _ = config.save();
-                        self_.imp().config.replace(config);
-                        self_.preview();
+                    });
+                    if let Some(id) = config.active_id() {
+                        if let Ok(theme) = Theme::load_from_id(&config, &id) {
+                            self_.open(theme, VariantSlot::new(dark, false));
+                        }
+                        let _ = config.apply_gtk4();
                     }
+                    self_.imp().config.replace(config);
+                    self_.preview();
                 }),
             );
             let dark = dark_settings.string("color-scheme").as_str() != "prefer-light";
-            match config {
-                Config::DarkLight {
+            match config.mode {
+                Mode::DarkLight {
                     ref mut is_dark, ..
                 } if *is_dark != dark => {
                     *is_dark = dark;
-                    let _ = match config.active_name() {
+                    let _ = match config.active_id() {
                         Some(n) if !n.is_empty() => config.apply_gtk4(),
-                        _ => Ok(()),
+                        _ => Ok(Vec::new()),
                     };
                 }
                 _ => {}
@@ -196,36 +265,33 @@ impl ColorOverridesEditor {
         {
             hc_settings.connect_changed(Some("high-contrast"), glib::clone!(@weak self_ => move |settings, _| {
                 let high_contrast = settings.boolean("high-contrast");
-
-                let mut config: Config = self_.imp().config.borrow().clone();
-                if match config {
-                    Config::DarkLight { ref mut is_high_contrast, .. } if *is_high_contrast != high_contrast => {
+                if !matches!(self_.imp().config.borrow().mode, Mode::DarkLight { is_high_contrast, .. } if is_high_contrast != high_contrast) {
+                    return;
+                }
+                let config = self_.update_config(|c| {
+                    if let Mode::DarkLight { is_high_contrast, .. } = &mut c.mode {
                         *is_high_contrast = high_contrast;
-                        if let Some(name) = config.active_name() {
-                            if let Ok(palette) = ColorOverrides::load_from_name(&name) {
-                                self_.imp().theme.replace(palette);
-                            }
-                            let _ = config.save();
-                            let _ = config.apply_gtk4();
-                        }
-                        true
-                    },
-                    _ => false
-                } {
-                    self_.imp().config.replace(config);
-                    self_.preview();
+                    }
+                });
+                if let (Some(id), Mode::DarkLight { is_dark, .. }) = (config.active_id(), &config.mode) {
+                    if let Ok(theme) = Theme::load_from_id(&config, &id) {
+                        self_.open(theme, VariantSlot::new(*is_dark, high_contrast));
+                    }
+                    let _ = config.apply_gtk4();
                 }
+                self_.imp().config.replace(config);
+                self_.preview();
             }));
             let high_contrast = hc_settings.boolean("high-contrast");
-            match config {
-                Config::DarkLight {
+            match config.mode {
+                Mode::DarkLight {
                     ref mut is_high_contrast,
                     ..
                 } if *is_high_contrast != high_contrast => {
                     *is_high_contrast = high_contrast;
-                    let _ = match config.active_name() {
+                    let _ = match config.active_id() {
                         Some(n) if !n.is_empty() => config.apply_gtk4(),
-                        _ => Ok(()),
+                        _ => Ok(Vec::new()),
                     };
                 }
                 _ => {}
@@ -235,38 +301,39 @@ impl ColorOverridesEditor {
 
         // init config widgets
         self_.set_config_widgets(&config_section, &config);
+        imp.config_section.set(config_section).unwrap();
 
-        dark_light_switch.connect_state_set(glib::clone!(@weak config_section, @weak self_=> @default-return gtk4::Inhibit(false), move |_, state| {
-            // cleanup existing widgets
-            while let Some(c) = config_section.first_child() {
-                config_section.remove(&c);
-            }
+        dark_light_switch.connect_state_set(
+            glib::clone!(@weak self_=> @default-return gtk4::Inhibit(false), move |_, state| {
+                if self_.imp().restoring.get() {
+                    return gtk4::Inhibit(false);
+                }
+                // TODO set dark light & high contrast depending on gsettings
+                // only the mode changes, the theme dirs and css options are kept
+                let mode = if state {
+                    Config::new_dark_light(true, false, "".into(), "".into()).mode
+                } else {
+                    Mode::Static { name: String::new() }
+                };
+                let config = self_.update_config(|c| c.mode = mode.clone());
+                self_.reset_config_widgets(&config);
+                self_.imp().config.replace(config);
+                gtk4::Inhibit(false)
+            }),
+        );
 
-            // TODO set dark light & high contrast depending on gsettings
-            if state {
-                let config = Config::new_dark_light(true, false, "".into(), "".into());
-                let _ = config.save();
-                self_.set_config_widgets(&config_section, &config);
-            } else {
-                let config = Config::new_static("".into(), false);
-                let _ = config.save();
-                self_.set_config_widgets(&config_section, &config);
-            }
-            gtk4::Inhibit(false)
+        // follow config changes made by other programs
+        theme_model::connect_config_changed(glib::clone!(@weak self_ => move |config| {
+            self_.imp().config.replace(config);
         }));
 
         load_dropdown.connect_closure(
             "theme-selected",
             false,
-            closure_local!(@weak-allow-none imp.name as name, @weak-allow-none imp.theme as theme, @weak-allow-none self_ => move |_file_button: ThemeDropdown, f: File| {
-                if let (Some(theme), Some(name), Some(Ok(t))) = (theme, name, f.path().as_ref().map(|p| ColorOverrides::load(p))) {
-                    let name = name.get().unwrap();
-                    name.set_text(&t.name);
-                    theme.replace(t);
-                    if let Some(self_) = self_ {
-                        self_.set_buttons();
-                        self_.preview();
-                    }
+            closure_local!(@weak-allow-none self_ => move |_file_button: ThemeDropdown, f: File| {
+                if let (Some(self_), Some(Ok(t))) = (self_, f.path().as_ref().map(|p| Theme::load(p))) {
+                    let slot = self_.imp().slot.get();
+                    self_.open(t, slot);
                 }
             }),
         );
@@ -291,12 +358,21 @@ impl ColorOverridesEditor {
         // set widget state
         imp.css_provider.set(provider).unwrap();
         imp.name.set(name).unwrap();
+        imp.about.set(about).unwrap();
+        imp.history.set(history).unwrap();
         imp.save.set(save_button).unwrap();
+        imp.revert.set(revert_button).unwrap();
+        imp.reset.set(reset_button).unwrap();
+        imp.read_only.set(read_only_label).unwrap();
         imp.file_button.set(file_button).unwrap();
         imp.color_editor.set(color_box).unwrap();
         imp.config.replace(config);
         imp.dark_light_switch.set(dark_light_switch).unwrap();
+        imp.variant_dropdown.set(variant_dropdown).unwrap();
+        generated_css_expander.set_child(Some(&self_.generated_css_pane()));
         self_.set_buttons();
+        self_.preview();
+        self_.connect_variant();
         self_.connect_name();
         self_.connect_control_buttons();
 
@@ -306,51 +382,111 @@ impl ColorOverridesEditor {
     fn connect_name(&self) {
         let imp = imp::ColorOverridesEditor::from_instance(self);
         imp.name.get().unwrap().connect_changed(
-            glib::clone!(@weak imp.theme as theme => move |name| {
+            glib::clone!(@weak imp.theme as theme, @weak imp.document as document => move |name| {
                 let name = name.text();
                 theme.borrow_mut().name = String::from(name.as_str());
+                document.borrow_mut().set_name(name.as_str());
             }),
         );
     }
 
+    fn connect_variant(&self) {
+        let imp = self.imp();
+        imp.variant_dropdown.get().unwrap().connect_selected_notify(
+            glib::clone!(@weak self as self_ => move |dropdown| {
+                let imp = self_.imp();
+                let slot = match VariantSlot::ALL.get(dropdown.selected() as usize) {
+                    Some(slot) if !imp.switching.get() => *slot,
+                    _ => return,
+                };
+                self_.store_variant();
+                imp.slot.set(slot);
+                imp.theme.replace(self_.variant_overrides(slot));
+                self_.set_buttons();
+                self_.preview();
+            }),
+        );
+    }
+
+    /// open `document` in the editor, showing the `preferred` variant if the theme has it
+    fn open(&self, document: Theme, preferred: VariantSlot) {
+        let imp = self.imp();
+        let slot = if document.get(preferred).is_some() {
+            preferred
+        } else {
+            document.slots().next().unwrap_or(preferred)
+        };
+        let name = document.name.clone();
+        imp.document.replace(document);
+        imp.name.get().unwrap().set_text(&name);
+        imp.about.get().unwrap().update();
+        imp.history.get().unwrap().update();
+        self.update_origin();
+        imp.slot.set(slot);
+        imp.theme.replace(self.variant_overrides(slot));
+
+        if let Some(position) = VariantSlot::ALL.iter().position(|s| *s == slot) {
+            imp.switching.set(true);
+            imp.variant_dropdown
+                .get()
+                .unwrap()
+                .set_selected(position as u32);
+            imp.switching.set(false);
+        }
+        self.set_buttons();
+        self.preview();
+    }
+
+    /// the overrides of the open theme for `slot`, or a starting point if the theme doesn't have that variant yet
+    fn variant_overrides(&self, slot: VariantSlot) -> ColorOverrides {
+        let document = self.imp().document.borrow();
+        let mut overrides = match document.get(slot) {
+            Some(o) => o.clone(),
+            None => Self::new_variant(&document, slot),
+        };
+        overrides.name = document.name.clone();
+        overrides
+    }
+
+    fn new_variant(document: &Theme, slot: VariantSlot) -> ColorOverrides {
+        match (slot, document.get(VariantSlot::new(slot.is_dark(), false))) {
+            (VariantSlot::HighContrastLight | VariantSlot::HighContrastDark, Some(base)) => {
+                base.clone()
+            }
+            _ if slot.is_dark() => ColorOverrides::dark_default(),
+            _ => ColorOverrides::light_default(),
+        }
+    }
+
+    /// store the edited overrides in the open theme, unless they are an untouched new variant
+    fn store_variant(&self) {
+        let imp = self.imp();
+        let slot = imp.slot.get();
+        let theme = imp.theme.borrow().clone();
+        let mut document = imp.document.borrow_mut();
+        let mut untouched = Self::new_variant(&document, slot);
+        untouched.name = theme.name.clone();
+        if document.get(slot).is_some() || theme != untouched {
+            document.set(slot, Some(theme));
+        }
+    }
+
+    /// replace the config widgets with widgets for `config`
+    fn reset_config_widgets(&self, config: &Config) {
+        let config_section = self.imp().config_section.get().unwrap();
+        while let Some(c) = config_section.first_child() {
+            config_section.remove(&c);
+        }
+        self.set_config_widgets(config_section, config);
+    }
+
     fn set_config_widgets(&self, config_box: &Box, config: &Config) {
-        match config {
-            Config::DarkLight {
+        match &config.mode {
+            Mode::DarkLight {
                 is_dark,
                 is_high_contrast,
                 ..
             } => {
-                view! {
-                    light_box = Box {
-                        set_orientation: Orientation::Horizontal,
-                        set_spacing: 4,
-                        set_margin_top: 4,
-                        set_margin_bottom: 4,
-                        set_margin_start: 4,
-                        set_margin_end: 4,
-
-                        append: light_theme_label = &Label {
-                            set_text: &fl!("current-light-theme"),
-                        },
-                        append: light_dropdown = &ThemeDropdown::new(Some(Watch::Light)),
-
-                    }
-                };
-                view! {
-                    dark_box = Box {
-                        set_orientation: Orientation::Horizontal,
-                        set_spacing: 4,
-                        set_margin_top: 4,
-                        set_margin_bottom: 4,
-                        set_margin_start: 4,
-                        set_margin_end: 4,
-
-                        append: dark_theme_label = &Label {
-                            set_text: &fl!("current-dark-theme"),
-                        },
-                        append: dark_dropdown = &ThemeDropdown::new(Some(Watch::Dark)),
-                    }
-                };
                 view! {
                     prefer_dark = Box {
                         set_orientation: Orientation::Horizontal,
@@ -381,68 +517,16 @@ impl ColorOverridesEditor {
                 };
                 cascade! {
                     config_box;
-                    ..append(&light_box);
-                    ..append(&dark_box);
+                    ..append(&self.slot_row(Watch::Light));
+                    ..append(&self.slot_row(Watch::Dark));
+                    ..append(&self.slot_row(Watch::HighContrastLight));
+                    ..append(&self.slot_row(Watch::HighContrastDark));
                     ..append(&prefer_dark);
                     ..append(&high_contrast);
                 };
                 dark_light_switch.set_state(*is_dark);
-                dark_light_switch.set_state(*is_high_contrast);
+                high_contrast_switch.set_state(*is_high_contrast);
 
-                // TODO init selection with config values
-                light_dropdown.connect_closure(
-                    "theme-selected",
-                    false,
-                    closure_local!(@weak-allow-none light_theme_label, @weak-allow-none self as self_  => move |_file_button: ThemeDropdown, f: File| {
-                        if let (Some(_), Some(name), Some(self_)) = (light_theme_label, f.basename(), self_) {
-                            let name = name.file_stem().unwrap().to_string_lossy();
-                            let mut c = self_.imp().config.borrow().clone();
-                            match c {
-                                Config::DarkLight { ref mut light, .. } => {*light = name.to_string();}
-                                _ => return,
-                            };
-                            if let Err(err) = {
-                                c.active_name();
-                                let _ = c.save();
-                                c.apply_gtk4()
-                            } {
-                                if let Some(window) = self_.root().and_then(|root| {
-                                    root.downcast::<Window>().ok()
-                                }) {
-                                    glib::MainContext::default().spawn_local(Self::dialog(window, format!("Warning to apply custom colors. {}", err)));
-                                };
-                            }
-                            self_.imp().config.replace(c);
-                        }
-                    }),
-                );
-
-                dark_dropdown.connect_closure(
-                    "theme-selected",
-                    false,
-                    closure_local!(@weak-allow-none dark_theme_label, @weak-allow-none self as self_ => move |_file_button: ThemeDropdown, f: File| {
-                        if let (Some(_), Some(name), Some(self_)) = (dark_theme_label, f.basename(), self_) {
-                            let name = name.file_stem().unwrap().to_string_lossy();
-                            let mut c = self_.imp().config.borrow().clone();
-                            match c {
-                                Config::DarkLight { ref mut dark, .. } => {*dark = name.to_string();}
-                                _ => return,
-                            };
-                            if let Err(err) = {
-                                c.active_name();
-                                let _ = c.save();
-                                c.apply_gtk4()
-                            } {
-                                if let Some(window) = self_.root().and_then(|root| {
-                                    root.downcast::<Window>().ok()
-                                }) {
-                                    glib::MainContext::default().spawn_local(Self::dialog(window, format!("Warning to apply custom colors. {}", err)));
-                                };
-                            }
-                            self_.imp().config.replace(c);
-                        }
-                    }),
-                );
                 dark_light_switch.connect_state_set(glib::clone!(@weak self as self_ => @default-return gtk4::Inhibit(false), move |_, state| {
                     if let Some(settings) = self_.imp().dark_settings.get() {
                         let _ = settings.set_string("color-scheme", if state {"prefer-dark"} else {"prefer-light"});
@@ -456,7 +540,7 @@ impl ColorOverridesEditor {
                     gtk4::Inhibit(false)
                 }));
             }
-            Config::Static { .. } => {
+            Mode::Static { .. } => {
                 view! {
                     theme_box = Box {
                         set_orientation: Orientation::Horizontal,
@@ -472,77 +556,299 @@ impl ColorOverridesEditor {
                         append: dropdown = &ThemeDropdown::new(Some(Watch::Static)),
                     }
                 };
-                view! {
-                    switch_box = Box {
-                        set_orientation: Orientation::Horizontal,
-                        set_spacing: 4,
-                        set_margin_top: 4,
-                        set_margin_bottom: 4,
-                        set_margin_start: 4,
-                        set_margin_end: 4,
-
-                        append = &Label {
-                            set_text: &fl!("apply-to-all-apps"),
-                        },
-                        append: switch = &Switch {},
-                    }
-                };
-                cascade! {
-                    config_box;
-                    ..append(&theme_box);
-                    ..append(&switch_box);
-                };
+                config_box.append(&theme_box);
 
                 dropdown.connect_closure(
                     "theme-selected",
                     false,
                     closure_local!(@weak-allow-none theme_label, @weak-allow-none self as self_ => move |_file_button: ThemeDropdown, f: File| {
-                        if let (Some(_), Some(name)) = (theme_label, f.basename()) {
-                            let name = name.file_stem().unwrap().to_string_lossy();
-                            user_colors::config::Config::set_active_light(&name).unwrap();
-                            if let Err(err) = Config::load().and_then(|c| match c.active_name() {
-                                Some(n) if !n.is_empty() => c.apply_gtk4(),
-                                _ => Ok(()),
-                            }) {
-                                if let Some(window) = self_.and_then(|self_| self_.root()).and_then(|root| {
-                                    root.downcast::<Window>().ok()
-                                }) {
-                                    glib::MainContext::default().spawn_local(Self::dialog(window, format!("Warning to apply custom colors. {}", err)));
-                                };
-                            }
+                        if let (Some(_), Some(self_)) = (theme_label, self_) {
+                            let name = theme_id(&f);
+                            self_.apply_config(|c| c.mode = Mode::Static { name: name.clone() });
                         }
                     }),
                 );
+            }
+        }
 
-                switch.connect_state_set(move |_, state| {
-                    let mut c = match Config::load() {
-                        Ok(c) => c,
-                        Err(_) => return gtk4::Inhibit(false),
-                    };
-                    match c {
-                        Config::DarkLight { .. } => return gtk4::Inhibit(false),
-                        Config::Static {
-                            ref mut apply_all, ..
-                        } => {
-                            *apply_all = state;
-                        }
-                    };
+        // the overrides can be imported for all applications in both modes
+        view! {
+            switch_box = Box {
+                set_orientation: Orientation::Horizontal,
+                set_spacing: 4,
+                set_margin_top: 4,
+                set_margin_bottom: 4,
+                set_margin_start: 4,
+                set_margin_end: 4,
 
-                    if c.active_name().is_some() {
-                        let _ = c.save();
-                        let _ = c.apply_gtk4();
+                append = &Label {
+                    set_text: &fl!("apply-to-all-apps"),
+                },
+                append: switch = &Switch {},
+                append: position = &DropDown {
+                    set_model: Some(&StringList::new(&[&fl!("gtk-css-top"), &fl!("gtk-css-bottom")])),
+                    set_tooltip_text: Some(&fl!("gtk-css-position")),
+                },
+            }
+        };
+        config_box.append(&switch_box);
+        view! {
+            custom_css_box = Box {
+                set_orientation: Orientation::Horizontal,
+                set_spacing: 4,
+                set_margin_top: 4,
+                set_margin_bottom: 4,
+                set_margin_start: 4,
+                set_margin_end: 4,
+
+                append = &Label {
+                    set_text: &fl!("apply-custom-css"),
+                },
+                append: custom_css_switch = &Switch {},
+            }
+        };
+        config_box.append(&custom_css_box);
+        switch.set_state(config.apply_all);
+        custom_css_switch.set_state(config.apply_custom_css);
+        custom_css_switch.set_sensitive(config.apply_all);
+        position.set_selected(match config.gtk_css_position {
+            BlockPosition::Top => 0,
+            BlockPosition::Bottom => 1,
+        });
+        position.set_sensitive(config.apply_all);
+
+        position.connect_selected_notify(glib::clone!(@weak self as self_ => move |position| {
+            let gtk_css_position = match position.selected() {
+                0 => BlockPosition::Top,
+                _ => BlockPosition::Bottom,
+            };
+            if gtk_css_position == self_.imp().config.borrow().gtk_css_position {
+                return;
+            }
+            self_.apply_config(|c| c.gtk_css_position = gtk_css_position);
+        }));
+
+        custom_css_switch.connect_state_set(glib::clone!(@weak self as self_ => @default-return gtk4::Inhibit(false), move |_, state| {
+            if self_.imp().config.borrow().apply_custom_css != state {
+                self_.apply_config(|c| c.apply_custom_css = state);
+            }
+            gtk4::Inhibit(false)
+        }));
+
+        switch.connect_state_set(glib::clone!(@weak self as self_, @weak position, @weak custom_css_switch => @default-return gtk4::Inhibit(false), move |_, state| {
+            position.set_sensitive(state);
+            custom_css_switch.set_sensitive(state);
+            if self_.imp().config.borrow().apply_all != state {
+                self_.apply_config(|c| c.apply_all = state);
+            }
+            gtk4::Inhibit(false)
+        }));
+    }
+
+    /// a labelled dropdown selecting the theme used for one slot of the dark / light config
+    fn slot_row(&self, watch: Watch) -> Box {
+        let label = match watch {
+            Watch::Light => fl!("current-light-theme"),
+            Watch::Dark => fl!("current-dark-theme"),
+            Watch::HighContrastLight => fl!("current-high-contrast-light-theme"),
+            Watch::HighContrastDark => fl!("current-high-contrast-dark-theme"),
+            Watch::Static => fl!("current-theme"),
+        };
+        view! {
+            slot_box = Box {
+                set_orientation: Orientation::Horizontal,
+                set_spacing: 4,
+                set_margin_top: 4,
+                set_margin_bottom: 4,
+                set_margin_start: 4,
+                set_margin_end: 4,
+
+                append = &Label {
+                    set_text: &label,
+                },
+                append: dropdown = &ThemeDropdown::new(Some(watch)),
+            }
+        };
+
+        dropdown.connect_closure(
+            "theme-selected",
+            false,
+            closure_local!(@weak-allow-none self as self_ => move |_file_button: ThemeDropdown, f: File| {
+                if let Some(self_) = self_ {
+                    self_.set_slot(watch, theme_id(&f));
+                    let mismatch = match watch.variant() {
+                        Some(Variant::Light) => Some(fl!("dark-theme-in-light-slot")),
+                        Some(Variant::Dark) => Some(fl!("light-theme-in-dark-slot")),
+                        None => None,
                     }
+                    .filter(|_| {
+                        theme_model::load(&f)
+                            .zip(watch.variant())
+                            .map(|(t, v)| !t.provides(v))
+                            .unwrap_or_default()
+                    });
+                    if let (Some(msg), Some(window)) = (mismatch, self_.root().and_then(|root| root.downcast::<Window>().ok())) {
+                        glib::MainContext::default().spawn_local(Self::dialog(window, msg));
+                    }
+                }
+            }),
+        );
 
-                    gtk4::Inhibit(false)
-                });
+        // high contrast slots are optional
+        if matches!(watch, Watch::HighContrastLight | Watch::HighContrastDark) {
+            let clear_button = Button::with_label(&fl!("clear"));
+            clear_button.connect_clicked(glib::clone!(@weak self as self_ => move |_| {
+                self_.set_slot(watch, String::new());
+            }));
+            slot_box.append(&clear_button);
+        }
+        slot_box
+    }
+
+    /// save and apply a new theme name for a slot of the dark / light config
+    fn set_slot(&self, watch: Watch, name: String) {
+        self.apply_config(|c| match (watch, &mut c.mode) {
+            (Watch::Light, Mode::DarkLight { light, .. }) => *light = name.clone(),
+            (Watch::Dark, Mode::DarkLight { dark, .. }) => *dark = name.clone(),
+            (
+                Watch::HighContrastLight,
+                Mode::DarkLight {
+                    high_contrast_light,
+                    ..
+                },
+            ) => *high_contrast_light = name.clone(),
+            (
+                Watch::HighContrastDark,
+                Mode::DarkLight {
+                    high_contrast_dark, ..
+                },
+            ) => *high_contrast_dark = name.clone(),
+            _ => {}
+        });
+    }
+
+    /// change the saved config with `f`, keeping changes made by other programs
+    ///
+    /// If it can't be saved, the change is only made to the shown config.
+    fn update_config(&self, mut f: impl FnMut(&mut Config)) -> Config {
+        Config::update(&mut f).unwrap_or_else(|_| {
+            let mut config = self.imp().config.borrow().clone();
+            f(&mut config);
+            config
+        })
+    }
+
+    /// change the saved config with `f` and apply it, restoring the previous colors unless the
+    /// user keeps the new ones in time
+    fn apply_config(&self, f: impl FnMut(&mut Config)) {
+        let previous = Config::applied_state();
+        let applied = Config::update(f).and_then(|config| {
+            match config.active_id() {
+                Some(n) if !n.is_empty() => config.apply_gtk4().map(|_| true),
+                _ => Ok(false),
             }
+            .map(|applied| (config, applied))
+        });
+        let applied = match (applied, &previous) {
+            (Ok((config, applied)), _) => {
+                self.imp().config.replace(config);
+                Ok(applied)
+            }
+            (Err(err), Ok(previous)) => {
+                // undo the config and the files written before the error
+                let _ = previous.restore();
+                self.show_config(Config::load().unwrap_or_default());
+                Err(err)
+            }
+            (Err(err), Err(_)) => Err(err),
+        };
+
+        let window = match self.root().and_then(|root| root.downcast::<Window>().ok()) {
+            Some(window) => window,
+            None => return,
+        };
+        match (applied, previous) {
+            (Err(err), _) => {
+                glib::MainContext::default().spawn_local(Self::dialog(
+                    window,
+                    fl!("apply-failed", error = err.to_string()),
+                ));
+            }
+            (Ok(true), Ok(previous)) => {
+                glib::MainContext::default()
+                    .spawn_local(self.clone().confirm_colors(window, previous));
+            }
+            _ => {}
+        }
+    }
+
+    /// ask whether to keep the applied colors, restoring `previous` unless the user answers in time
+    async fn confirm_colors(self, window: Window, previous: AppliedState) {
+        let confirm = MessageDialog::builder()
+            .transient_for(&window)
+            .modal(true)
+            .text(&fl!("keep-colors"))
+            .secondary_text(&fl!("keep-colors-countdown", seconds = CONFIRM_SECONDS))
+            .build();
+        confirm.add_buttons(&[
+            (&fl!("revert-colors"), gtk4::ResponseType::Reject),
+            (&fl!("keep-colors-button"), gtk4::ResponseType::Accept),
+        ]);
+
+        // the new colors may make the dialog unreadable, so not answering reverts them
+        let remaining = Rc::new(Cell::new(CONFIRM_SECONDS));
+        glib::timeout_add_seconds_local(
+            1,
+            glib::clone!(@weak confirm, @strong remaining => @default-return glib::Continue(false), move || {
+                // stopped after an answer
+                if remaining.get() == 0 {
+                    return glib::Continue(false);
+                }
+                remaining.set(remaining.get() - 1);
+                if remaining.get() == 0 {
+                    confirm.response(gtk4::ResponseType::Reject);
+                    return glib::Continue(false);
+                }
+                confirm.set_secondary_text(Some(&fl!("keep-colors-countdown", seconds = remaining.get())));
+                glib::Continue(true)
+            }),
+        );
+        let response = confirm.run_future().await;
+        remaining.set(0);
+        confirm.close();
+        if response == gtk4::ResponseType::Accept {
+            return;
+        }
+
+        if let Err(err) = previous.restore() {
+            Self::dialog(
+                window,
+                fl!("restore-colors-failed", error = err.to_string()),
+            )
+            .await;
+            return;
         }
+        self.show_config(Config::load().unwrap_or_default());
+    }
+
+    /// show `config` without saving it, e.g. after it was restored
+    fn show_config(&self, config: Config) {
+        let imp = self.imp();
+        imp.restoring.set(true);
+        imp.dark_light_switch
+            .get()
+            .unwrap()
+            .set_state(matches!(config.mode, Mode::DarkLight { .. }));
+        imp.restoring.set(false);
+        self.reset_config_widgets(&config);
+        imp.config.replace(config);
     }
 
     fn set_buttons(&self) {
         let imp = imp::ColorOverridesEditor::from_instance(self);
 
         let color_editor = imp.color_editor.get().unwrap();
+        imp.color_buttons.borrow_mut().clear();
         let mut c = color_editor.first_child();
         while let Some(child) = c {
             color_editor.remove(&child);
@@ -736,6 +1042,106 @@ impl ColorOverridesEditor {
         color_editor.append(&card_section);
         color_editor.append(&popover_section);
         color_editor.append(&misc_section);
+        color_editor.append(&self.custom_css_section());
+    }
+
+    /// a code view editing the custom css of the variant, which is only previewed while it is valid
+    fn custom_css_section(&self) -> ExpanderRow {
+        let imp = self.imp();
+        let custom_css = imp.theme.borrow().custom_css.clone();
+        let section = ExpanderRow::builder()
+            .name(&fl!("custom-css"))
+            .expanded(custom_css.is_some())
+            .enable_expansion(true)
+            .title(&fl!("custom-css"))
+            .hexpand(true)
+            .build();
+
+        let text_view = cascade! {
+            TextView::new();
+            ..set_monospace(true);
+            ..set_wrap_mode(WrapMode::WordChar);
+            ..set_top_margin(4);
+            ..set_bottom_margin(4);
+            ..set_left_margin(4);
+            ..set_right_margin(4);
+        };
+        let scrolled_window = cascade! {
+            ScrolledWindow::new();
+            ..set_min_content_height(160);
+            ..set_hexpand(true);
+            ..set_child(Some(&text_view));
+        };
+        view! {
+            css_box = Box {
+                set_orientation: Orientation::Vertical,
+                set_spacing: 4,
+                set_margin_top: 4,
+                set_margin_bottom: 4,
+                set_margin_start: 4,
+                set_margin_end: 4,
+
+                append = &Label {
+                    set_text: &fl!("custom-css-details"),
+                    set_xalign: 0.0,
+                    set_wrap: true,
+                    add_css_class: "dim-label",
+                },
+                append: &scrolled_window,
+                append: error_label = &Label {
+                    set_xalign: 0.0,
+                    set_wrap: true,
+                    add_css_class: "error",
+                },
+            }
+        };
+        section.add_row(&css_box);
+
+        let buffer = text_view.buffer();
+        buffer.set_text(custom_css.as_deref().unwrap_or_default());
+        imp.custom_css.replace(Some(buffer.clone()));
+        let error = custom_css.as_deref().and_then(Self::custom_css_error);
+        error_label.set_text(error.as_deref().unwrap_or_default());
+        error_label.set_visible(error.is_some());
+
+        buffer.connect_changed(
+            glib::clone!(@weak self as self_, @weak error_label => move |buffer| {
+                let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+                let error = Self::custom_css_error(&text);
+                error_label.set_text(error.as_deref().unwrap_or_default());
+                error_label.set_visible(error.is_some());
+                if self_.imp().syncing.get() {
+                    return;
+                }
+                // kept while it is invalid, so it isn't lost while typing
+                self_.imp().theme.borrow_mut().custom_css =
+                    Some(text.to_string()).filter(|css| !css.trim().is_empty());
+                if error.is_none() {
+                    self_.preview();
+                }
+            }),
+        );
+        section
+    }
+
+    /// why `css` can't be used as custom css, or the first error GTK reports when parsing it
+    fn custom_css_error(css: &str) -> Option<String> {
+        if let Err(err) = check_custom_css(css) {
+            return Some(err.to_string());
+        }
+        let error = Rc::new(RefCell::new(None));
+        let provider = CssProvider::new();
+        provider.connect_parsing_error(glib::clone!(@strong error => move |_, section, err| {
+            if error.borrow().is_none() {
+                error.replace(Some(fl!(
+                    "custom-css-parse-error",
+                    line = section.start_location().lines() + 1,
+                    error = err.to_string()
+                )));
+            }
+        }));
+        provider.load_from_data(css.as_bytes());
+        error.take()
     }
 
     fn get_color_button(&self, id: &str, label: &str) -> Box {
@@ -748,32 +1154,38 @@ impl ColorOverridesEditor {
             ..set_title(label);
             ..set_use_alpha(true);
         };
-        if let Some(Ok(c)) = imp.theme.borrow().get_key(id).map(|c| RGBA::parse(&c)) {
-            color_button.set_rgba(&c);
-        } else {
-            color_button.set_rgba(&RGBA::new(0.0, 0.0, 0.0, 0.0));
-        }
+        color_button.set_rgba(&Self::color_rgba(&imp.theme.borrow(), id));
+        imp.color_buttons
+            .borrow_mut()
+            .insert(id.to_string(), color_button.clone());
         let id_clone = id.to_string();
         color_button
         .connect_rgba_notify(glib::clone!(@weak imp.theme as theme, @weak self as self_ => move |color_button| {
+            if self_.imp().syncing.get() {
+                return;
+            }
             {
                 let mut t = theme.borrow_mut();
                 t.set_key(&id_clone, Some(hex_from_rgba(color_button.rgba()))).unwrap_or_else(|_| panic!("Failed to set {}", id_clone));
             }
             self_.preview();
         }));
-        let clear_button = Button::with_label("Clear");
+        let clear_button = Button::with_label(&fl!("clear"));
         clear_button.add_css_class("destructive-action");
         clear_button.set_halign(Align::End);
         let id_clone = id.to_string();
         clear_button.connect_clicked(
-            glib::clone!(@weak color_button, @weak imp.theme as theme => move |_| {
+            glib::clone!(@weak color_button, @weak imp.theme as theme, @weak self as self_ => move |_| {
                 {
                     let mut t = theme.borrow_mut();
                     t.set_key(&id_clone, None).unwrap_or_else(|_| panic!("Failed to set {id_clone}"));
                     drop(t);
+                    // the transparent placeholder is not the color of the key
+                    self_.imp().syncing.set(true);
                     color_button.set_rgba(&RGBA::new(0.0, 0.0, 0.0, 0.0));
+                    self_.imp().syncing.set(false);
                 }
+                self_.preview();
             }),
         );
         view! {
@@ -802,24 +1214,183 @@ impl ColorOverridesEditor {
         color_box
     }
 
+    /// the color of `id` for a color button, transparent if it is unset or not a plain color
+    fn color_rgba(theme: &ColorOverrides, id: &str) -> RGBA {
+        match theme.get_key(id).map(|c| RGBA::parse(&c)) {
+            Some(Ok(c)) => c,
+            _ => RGBA::new(0.0, 0.0, 0.0, 0.0),
+        }
+    }
+
+    /// the css of the variant as it is applied, edited `@define-color` lines change the colors
+    fn generated_css_pane(&self) -> Box {
+        let text_view = cascade! {
+            TextView::new();
+            ..set_monospace(true);
+            ..set_wrap_mode(WrapMode::WordChar);
+            ..set_top_margin(4);
+            ..set_bottom_margin(4);
+            ..set_left_margin(4);
+            ..set_right_margin(4);
+        };
+        let scrolled_window = cascade! {
+            ScrolledWindow::new();
+            ..set_min_content_height(240);
+            ..set_hexpand(true);
+            ..set_child(Some(&text_view));
+        };
+        view! {
+            pane = Box {
+                set_orientation: Orientation::Vertical,
+                set_spacing: 4,
+                set_margin_top: 4,
+                set_margin_bottom: 4,
+                set_margin_start: 4,
+                set_margin_end: 4,
+
+                append = &Label {
+                    set_text: &fl!("generated-css-details"),
+                    set_xalign: 0.0,
+                    set_wrap: true,
+                    add_css_class: "dim-label",
+                },
+                append: &scrolled_window,
+                append: error_label = &Label {
+                    set_xalign: 0.0,
+                    set_wrap: true,
+                    add_css_class: "error",
+                    set_visible: false,
+                },
+                append: copy_button = &Button {
+                    set_halign: Align::Start,
+                    set_label: &fl!("copy-css"),
+                },
+            }
+        };
+
+        copy_button.connect_clicked(
+            glib::clone!(@weak self as self_, @weak text_view => move |_| {
+                let buffer = text_view.buffer();
+                self_
+                    .clipboard()
+                    .set_text(&buffer.text(&buffer.start_iter(), &buffer.end_iter(), false));
+            }),
+        );
+
+        text_view.buffer().connect_changed(
+            glib::clone!(@weak self as self_, @weak error_label => move |buffer| {
+                let imp = self_.imp();
+                if imp.syncing.get() {
+                    return;
+                }
+                let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+                let mut theme = imp.theme.borrow().clone();
+                let result = theme.set_gtk_css(&text);
+                error_label.set_visible(result.is_err());
+                if let Err(err) = result {
+                    error_label.set_text(&err.to_string());
+                    return;
+                }
+                if theme == *imp.theme.borrow() {
+                    return;
+                }
+                imp.theme.replace(theme);
+                self_.sync_widgets();
+                // the pane is left as typed, it is regenerated on the next change elsewhere
+                imp.css_provider
+                    .get()
+                    .unwrap()
+                    .load_from_data(imp.theme.borrow().as_gtk_css().as_bytes());
+            }),
+        );
+
+        self.imp().generated_css.set(text_view).unwrap();
+        self.imp().generated_css_error.set(error_label).unwrap();
+        pane
+    }
+
+    /// show the colors and custom css of the variant after they were changed in the generated css
+    fn sync_widgets(&self) {
+        let imp = self.imp();
+        let theme = imp.theme.borrow().clone();
+        imp.syncing.set(true);
+        for (id, button) in imp.color_buttons.borrow().iter() {
+            button.set_rgba(&Self::color_rgba(&theme, id));
+        }
+        if let Some(buffer) = imp.custom_css.borrow().as_ref() {
+            let custom_css = theme.custom_css.as_deref().unwrap_or_default();
+            if buffer
+                .text(&buffer.start_iter(), &buffer.end_iter(), false)
+                .as_str()
+                != custom_css
+            {
+                buffer.set_text(custom_css);
+            }
+        }
+        imp.syncing.set(false);
+    }
+
+    /// replace the generated css with `css`, e.g. after a color was changed
+    fn show_generated_css(&self, css: &str) {
+        let imp = self.imp();
+        imp.syncing.set(true);
+        imp.generated_css.get().unwrap().buffer().set_text(css);
+        imp.syncing.set(false);
+        imp.generated_css_error.get().unwrap().set_visible(false);
+    }
+
     fn connect_control_buttons(&self) {
         let imp = imp::ColorOverridesEditor::from_instance(self);
         let theme = &imp.theme;
 
+        imp.history.get().unwrap().connect_closure(
+            "restore-revision",
+            false,
+            closure_local!(@weak-allow-none self as self_ => move |_history: ThemeHistory, time: u64| {
+                if let Some(self_) = self_ {
+                    self_.restore_revision(time);
+                }
+            }),
+        );
+
+        imp.reset
+            .get()
+            .unwrap()
+            .connect_clicked(glib::clone!(@weak self as self_ => move |_| {
+                if let Some(window) = self_.root().and_then(|root| root.downcast::<Window>().ok()) {
+                    glib::MainContext::default().spawn_local(self_.clone().confirm_reset(window));
+                }
+            }));
+
+        imp.revert
+            .get()
+            .unwrap()
+            .connect_clicked(glib::clone!(@weak self as self_ => move |_| {
+                let id = self_.imp().document.borrow().id.clone();
+                let store = self_.imp().config.borrow().theme_store();
+                match store.and_then(|mut store| store.revert(&id)) {
+                    Ok(theme) => {
+                        self_.open(theme, self_.imp().slot.get());
+                        self_.apply_active();
+                    }
+                    Err(err) => {
+                        if let Some(window) = self_.root().and_then(|root| root.downcast::<Window>().ok()) {
+                            glib::MainContext::default().spawn_local(Self::dialog(window, fl!("revert-failed", error = err.to_string())));
+                        }
+                    }
+                }
+            }));
+
         imp.save.get().unwrap().connect_clicked(
             glib::clone!(@weak theme, @weak self as self_ => move |_| {
                 if !theme.borrow().name.is_empty() {
-                    // TODO toast if fails
-                    let _ = theme.borrow().save();
-                    if let Err(err) = Config::load().and_then(|c| match c.active_name() {
-                        Some(n) if !n.is_empty() => c.apply_gtk4(),
-                        _ => Ok(()),
-                    }) {
-                        if let Some(window) = self_.root().and_then(|root| {
-                            root.downcast::<Window>().ok()
-                        }) {
-                            glib::MainContext::default().spawn_local(Self::dialog(window, format!("Warning to apply custom colors. {}", err)));
-                        };
+                    self_.store_variant();
+                    let diagnostics = lint(&self_.imp().document.borrow());
+                    match self_.root().and_then(|root| root.downcast::<Window>().ok()) {
+                        Some(window) => {
+                            glib::MainContext::default().spawn_local(self_.clone().confirm_save(window, diagnostics));
+                        }
+                        None => self_.save_document(),
                     }
                 } else {
                     // todo replace with toast
@@ -838,15 +1409,146 @@ impl ColorOverridesEditor {
         );
     }
 
+    /// replace the open theme with its revision from `time`, keeping the current version as a revision
+    fn restore_revision(&self, time: u64) {
+        let id = self.imp().document.borrow().id.clone();
+        let restored = History::new()
+            .and_then(|history| history.revision(&id, time))
+            .and_then(|revision| {
+                self.imp()
+                    .config
+                    .borrow()
+                    .theme_store()
+                    .and_then(|mut store| History::restore(&mut store, &revision))
+            });
+        match restored {
+            Ok(theme) => {
+                self.open(theme, self.imp().slot.get());
+                self.apply_active();
+            }
+            Err(err) => {
+                if let Some(window) = self.root().and_then(|root| root.downcast::<Window>().ok()) {
+                    glib::MainContext::default().spawn_local(Self::dialog(
+                        window,
+                        fl!("restore-revision-failed", error = err.to_string()),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// show whether the open theme is read-only or shadows a theme from another dir
+    fn update_origin(&self) {
+        let imp = self.imp();
+        let id = imp.document.borrow().id.clone();
+        let store = imp.config.borrow().theme_store().ok();
+        let origin = store.as_ref().and_then(|store| store.origin(&id));
+        let is_read_only = origin
+            .as_ref()
+            .map(|origin| !origin.is_writable())
+            .unwrap_or_default();
+        let shadows = store
+            .as_ref()
+            .map(|store| store.shadows(&id))
+            .unwrap_or_default();
+
+        let read_only = imp.read_only.get().unwrap();
+        read_only.set_text(&match origin {
+            Some(Origin::Extra { dir, .. }) => {
+                fl!("theme-dir-read-only", dir = dir.display().to_string())
+            }
+            _ => fl!("system-theme-read-only"),
+        });
+        read_only.set_visible(is_read_only);
+        imp.save.get().unwrap().set_label(&if is_read_only {
+            fl!("save-theme-copy")
+        } else {
+            fl!("save-theme")
+        });
+        imp.revert.get().unwrap().set_visible(shadows);
+    }
+
+    /// save the open theme, as a user theme shadowing it if it is read-only
+    fn save_document(&self) {
+        let imp = self.imp();
+        let mut document = imp.document.borrow_mut();
+        // system themes and themes in read-only dirs keep their id, they are saved to the user
+        // dir where they shadow the original until it is reverted
+        document.touch();
+        let saved = document.save(&imp.config.borrow());
+        drop(document);
+        if let Err(err) = saved {
+            if let Some(window) = self.root().and_then(|root| root.downcast::<Window>().ok()) {
+                glib::MainContext::default().spawn_local(Self::dialog(
+                    window,
+                    fl!("save-failed", error = err.to_string()),
+                ));
+            }
+            return;
+        }
+        imp.about.get().unwrap().update();
+        imp.history.get().unwrap().update();
+        self.update_origin();
+        self.apply_active();
+    }
+
+    /// save the open theme, asking first if the linter found problems in it
+    async fn confirm_save(self, window: Window, diagnostics: Vec<Diagnostic>) {
+        if confirm_diagnostics(&window, &diagnostics, &fl!("save-anyway")).await {
+            self.save_document();
+        }
+    }
+
+    /// apply the active theme of the config, e.g. after it was saved
+    fn apply_active(&self) {
+        let message = match Config::load().and_then(|c| match c.active_id() {
+            Some(n) if !n.is_empty() => c.apply_gtk4(),
+            _ => Ok(Vec::new()),
+        }) {
+            Ok(rejected) if rejected.is_empty() => return,
+            Ok(rejected) => {
+                let keys: Vec<&str> = rejected.iter().map(|r| r.key).collect();
+                fl!("colors-rejected", keys = keys.join(", "))
+            }
+            // e.g. deleted by hand, the user has to pick another one
+            Err(Error::ThemeNotFound { name }) => fl!("active-theme-missing", name = name),
+            Err(err) => fl!("apply-failed", error = err.to_string()),
+        };
+        if let Some(window) = self.root().and_then(|root| root.downcast::<Window>().ok()) {
+            glib::MainContext::default().spawn_local(Self::dialog(window, message));
+        };
+    }
+
     fn preview(&self) {
         let imp = self.imp();
-        let theme = self.imp().theme.borrow();
-        let preview_css = &mut theme.as_gtk_css();
-        preview_css.push_str(&imp.theme.borrow().as_gtk_css());
+        let preview_css = imp.theme.borrow().as_gtk_css();
         imp.css_provider
             .get()
             .unwrap()
             .load_from_data(preview_css.as_bytes());
+        self.show_generated_css(&preview_css);
+    }
+
+    /// remove all customizations after asking, see [`Config::reset_all`]
+    async fn confirm_reset(self, window: Window) {
+        let confirm = MessageDialog::builder()
+            .transient_for(&window)
+            .modal(true)
+            .buttons(gtk4::ButtonsType::OkCancel)
+            .text(&fl!("reset-all-confirm"))
+            .secondary_text(&fl!("reset-all-details"))
+            .build();
+        let response = confirm.run_future().await;
+        confirm.close();
+        if response != gtk4::ResponseType::Ok {
+            return;
+        }
+
+        if let Err(err) = Config::reset_all() {
+            Self::dialog(window, fl!("reset-all-failed", error = err.to_string())).await;
+            return;
+        }
+        self.show_config(Config::default());
     }
 
     async fn dialog<T: Display>(window: Window, msg: T) {
@@ -3,5 +3,7 @@
 pub use color_overrides_editor::*;
 
 mod color_overrides_editor;
+mod theme_about;
 mod theme_dropdown;
+mod theme_history;
 mod theme_import_button;
@@ -0,0 +1,43 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+use gtk4::{glib, subclass::prelude::*, Entry, Label};
+use once_cell::sync::OnceCell;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+use user_colors::theme::Theme;
+
+// Object holding the state
+#[derive(Default)]
+pub struct ThemeAbout {
+    /// the theme open in the editor
+    pub document: OnceCell<Rc<RefCell<Theme>>>,
+    pub author: OnceCell<Entry>,
+    pub description: OnceCell<Entry>,
+    pub license: OnceCell<Entry>,
+    pub tags: OnceCell<Entry>,
+    pub origin: OnceCell<Entry>,
+    pub variants: OnceCell<Label>,
+    pub created: OnceCell<Label>,
+    pub modified: OnceCell<Label>,
+    /// set while the entries are filled from the document
+    pub updating: Cell<bool>,
+}
+
+// The central trait for subclassing a GObject
+#[glib::object_subclass]
+impl ObjectSubclass for ThemeAbout {
+    const NAME: &'static str = "ThemeAboutWidget";
+    type Type = super::ThemeAbout;
+    type ParentType = gtk4::Box;
+}
+
+// Trait shared by all GObjects
+impl ObjectImpl for ThemeAbout {}
+
+// Trait shared by all widgets
+impl WidgetImpl for ThemeAbout {}
+
+// Trait shared by all boxes
+impl BoxImpl for ThemeAbout {}
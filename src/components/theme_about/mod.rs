@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+use adw::{traits::ExpanderRowExt, ExpanderRow};
+use gtk4::{glib, prelude::*, subclass::prelude::*, Box, Entry, Label, Orientation};
+use once_cell::sync::OnceCell;
+use relm4_macros::view;
+use std::{cell::RefCell, rc::Rc};
+use user_colors::theme::{Metadata, Theme, VariantSlot};
+
+use crate::{fl, util::format_time};
+mod imp;
+
+glib::wrapper! {
+    pub struct ThemeAbout(ObjectSubclass<imp::ThemeAbout>)
+        @extends gtk4::Box, gtk4::Widget,
+    @implements gtk4::Accessible, gtk4::Buildable, gtk4::ConstraintTarget, gtk4::Orientable;
+}
+
+impl ThemeAbout {
+    /// an "About this theme" panel editing the metadata of `document`
+    pub fn new(document: Rc<RefCell<Theme>>) -> Self {
+        let self_: Self = glib::Object::new(&[]).expect("Failed to create Theme About");
+
+        let about_section = ExpanderRow::builder()
+            .title(&fl!("about-theme"))
+            .enable_expansion(true)
+            .expanded(false)
+            .hexpand(true)
+            .build();
+
+        let author = self_.entry_row(&about_section, &fl!("theme-author"), |m, v| m.author = v);
+        let description = self_.entry_row(&about_section, &fl!("theme-description"), |m, v| {
+            m.description = v
+        });
+        let license = self_.entry_row(&about_section, &fl!("theme-license"), |m, v| m.license = v);
+        let tags = self_.entry_row(&about_section, &fl!("theme-tags"), |m, v| {
+            m.tags = v
+                .iter()
+                .flat_map(|v| v.split(','))
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect();
+        });
+        tags.set_placeholder_text(Some(&fl!("theme-tags-placeholder")));
+        let origin = self_.entry_row(&about_section, &fl!("theme-origin"), |m, v| m.origin = v);
+        let variants = Self::label_row(&about_section, &fl!("theme-variants"));
+        let created = Self::label_row(&about_section, &fl!("theme-created"));
+        let modified = Self::label_row(&about_section, &fl!("theme-modified"));
+
+        self_.append(&about_section);
+
+        let imp = self_.imp();
+        imp.document.set(document).unwrap();
+        imp.author.set(author).unwrap();
+        imp.description.set(description).unwrap();
+        imp.license.set(license).unwrap();
+        imp.tags.set(tags).unwrap();
+        imp.origin.set(origin).unwrap();
+        imp.variants.set(variants).unwrap();
+        imp.created.set(created).unwrap();
+        imp.modified.set(modified).unwrap();
+        self_.update();
+
+        self_
+    }
+
+    /// show the metadata of the document, e.g. after a different theme was opened or it was saved
+    pub fn update(&self) {
+        let imp = self.imp();
+        let document = imp.document.get().unwrap().borrow();
+        let metadata = &document.metadata;
+
+        imp.updating.set(true);
+        let text = |entry: &OnceCell<Entry>, value: &Option<String>| {
+            entry
+                .get()
+                .unwrap()
+                .set_text(value.as_deref().unwrap_or_default())
+        };
+        text(&imp.author, &metadata.author);
+        text(&imp.description, &metadata.description);
+        text(&imp.license, &metadata.license);
+        text(&imp.origin, &metadata.origin);
+        imp.tags.get().unwrap().set_text(&metadata.tags.join(", "));
+        imp.updating.set(false);
+
+        let variants: Vec<String> = document
+            .slots()
+            .map(|slot| match slot {
+                VariantSlot::Light => fl!("light-variant"),
+                VariantSlot::Dark => fl!("dark-variant"),
+                VariantSlot::HighContrastLight => fl!("high-contrast-light-variant"),
+                VariantSlot::HighContrastDark => fl!("high-contrast-dark-variant"),
+            })
+            .collect();
+        imp.variants.get().unwrap().set_text(&variants.join(", "));
+        imp.created
+            .get()
+            .unwrap()
+            .set_text(&format_time(metadata.created));
+        imp.modified
+            .get()
+            .unwrap()
+            .set_text(&format_time(metadata.modified));
+    }
+
+    /// a labelled entry which writes its text into the document metadata with `set`
+    fn entry_row(
+        &self,
+        section: &ExpanderRow,
+        label: &str,
+        set: impl Fn(&mut Metadata, Option<String>) + 'static,
+    ) -> Entry {
+        view! {
+            row = Box {
+                set_orientation: Orientation::Horizontal,
+                set_spacing: 4,
+                set_margin_top: 4,
+                set_margin_bottom: 4,
+                set_margin_start: 4,
+                set_margin_end: 4,
+
+                append = &Label {
+                    set_text: label,
+                },
+                append: entry = &Entry {
+                    set_hexpand: true,
+                },
+            }
+        };
+        section.add_row(&row);
+
+        entry.connect_changed(glib::clone!(@weak self as self_ => move |entry| {
+            let imp = self_.imp();
+            if imp.updating.get() {
+                return;
+            }
+            let text = entry.text();
+            let value = Some(text.trim().to_string()).filter(|v| !v.is_empty());
+            set(&mut imp.document.get().unwrap().borrow_mut().metadata, value);
+        }));
+        entry
+    }
+
+    fn label_row(section: &ExpanderRow, label: &str) -> Label {
+        view! {
+            row = Box {
+                set_orientation: Orientation::Horizontal,
+                set_spacing: 4,
+                set_margin_top: 4,
+                set_margin_bottom: 4,
+                set_margin_start: 4,
+                set_margin_end: 4,
+
+                append = &Label {
+                    set_text: label,
+                },
+                append: value = &Label {
+                    set_hexpand: true,
+                    set_xalign: 0.0,
+                    set_selectable: true,
+                },
+            }
+        };
+        section.add_row(&row);
+        value
+    }
+}
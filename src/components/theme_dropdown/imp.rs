@@ -1,14 +1,29 @@
 use gtk4::{
     gio::File,
-    glib::{self, subclass::Signal},
-    prelude::StaticType,
+    glib::{self, subclass::Signal, SignalHandlerId},
+    prelude::*,
     subclass::prelude::*,
+    DropDown,
 };
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
+use std::cell::{Cell, RefCell};
+
+use super::Watch;
 
 // Object holding the state
 #[derive(Default)]
-pub struct ThemeDropdown {}
+pub struct ThemeDropdown {
+    pub dropdown: OnceCell<DropDown>,
+    /// tags of all themes, for filtering
+    pub tags: OnceCell<DropDown>,
+    /// only themes with this tag are listed
+    pub tag: RefCell<Option<String>>,
+    pub watch: Cell<Option<Watch>>,
+    /// set while the selection is changed to follow the config
+    pub syncing: Cell<bool>,
+    /// handlers connected to the shared theme model and config monitor
+    pub handlers: RefCell<Vec<(glib::Object, SignalHandlerId)>>,
+}
 
 // The central trait for subclassing a GObject
 #[glib::object_subclass]
@@ -29,6 +44,12 @@ impl ObjectImpl for ThemeDropdown {
         });
         SIGNALS.as_ref()
     }
+
+    fn dispose(&self, _obj: &Self::Type) {
+        for (object, handler) in self.handlers.take() {
+            object.disconnect(handler);
+        }
+    }
 }
 
 // Trait shared by all widgets
@@ -1,117 +1,128 @@
 // SPDX-License-Identifier: MPL-2.0-only
 
-use std::path::PathBuf;
-
 use gtk4::{
     ffi::GTK_INVALID_LIST_POSITION,
+    gdk::RGBA,
     gio::{File, ListStore},
     glib::{self, clone},
     prelude::*,
-    DropDown, Label, SignalListItemFactory,
+    subclass::prelude::*,
+    Align, Box, ClosureExpression, CustomFilter, CustomSorter, DrawingArea, DropDown, FilterChange,
+    FilterListModel, Image, Label, Orientation, SignalListItemFactory, SortListModel, StringList,
+    StringObject,
+};
+use relm4_macros::view;
+use std::collections::BTreeMap;
+use user_colors::{
+    colors::{ColorOverrides, Variant},
+    config::{Config, Mode},
+    store::Origin,
+};
+
+use crate::{
+    fl,
+    theme_model::{self, theme_id, theme_name},
+    util::draw_swatch,
 };
-use itertools::Itertools;
-use user_colors::{config::Config, NAME, THEME_DIR};
 mod imp;
 
+/// keys shown in the swatch strip of each theme
+const SWATCH_KEYS: [&str; 4] = [
+    "window_bg_color",
+    "accent_bg_color",
+    "destructive_bg_color",
+    "success_color",
+];
+const SWATCH_SIZE: i32 = 12;
+
 glib::wrapper! {
     pub struct ThemeDropdown(ObjectSubclass<imp::ThemeDropdown>)
         @extends gtk4::Box, gtk4::Widget,
     @implements gtk4::Accessible, gtk4::Buildable, gtk4::ConstraintTarget, gtk4::Orientable;
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Watch {
     Light,
     Dark,
+    HighContrastLight,
+    HighContrastDark,
     Static,
 }
 
+impl Watch {
+    /// the variant of themes meant for this slot
+    pub fn variant(self) -> Option<Variant> {
+        match self {
+            Watch::Light | Watch::HighContrastLight => Some(Variant::Light),
+            Watch::Dark | Watch::HighContrastDark => Some(Variant::Dark),
+            Watch::Static => None,
+        }
+    }
+}
+
 impl ThemeDropdown {
     pub fn new(watch: Option<Watch>) -> Self {
         let self_: Self = glib::Object::new(&[]).expect("Failed to create Theme Dropdown");
 
-        let model = ListStore::new(File::static_type());
+        let themes = theme_model::themes();
+        let sorted = Self::sorted(&themes, watch.and_then(Watch::variant));
+        let filter = CustomFilter::new(
+            clone!(@weak self_ => @default-return true, move |o: &glib::Object| {
+                match self_.imp().tag.borrow().as_deref() {
+                    Some(tag) => o
+                        .downcast_ref::<File>()
+                        .and_then(theme_model::load)
+                        .map(|t| t.has_tag(tag))
+                        .unwrap_or_default(),
+                    None => true,
+                }
+            }),
+        );
+        let model = FilterListModel::new(Some(&sorted), Some(&filter));
 
         let factory = SignalListItemFactory::new();
         factory.connect_setup(move |_, list_item| {
-            let label = Label::new(None);
-            list_item.set_child(Some(&label));
+            list_item.set_child(Some(&Self::row()));
         });
 
         factory.connect_bind(move |_, list_item| {
-            // Get `IntegerObject` from `ListItem`
             let file = list_item
                 .item()
                 .expect("The item has to exist.")
                 .downcast::<File>()
-                .expect("The item has to be an `GString`.");
+                .expect("The item has to be a `File`.");
 
-            // Get `Label` from `ListItem`
-            let label = list_item
+            let row = list_item
                 .child()
                 .expect("The child has to exist.")
-                .downcast::<Label>()
-                .expect("The child has to be a `Label`.");
-
-            // Set "label" to "number"
-            let basename = file.basename().unwrap_or_default();
-            let name = basename
-                .file_stem()
-                .map(|s| s.to_string_lossy())
-                .unwrap_or_default();
+                .downcast::<Box>()
+                .expect("The child has to be a `Box`.");
 
-            label.set_label(&name);
+            Self::bind_row(&row, &file, watch);
         });
 
+        // search by theme name
+        let expression = ClosureExpression::new::<String, _, _>(
+            &[] as &[gtk4::Expression],
+            glib::closure!(|file: File| theme_name(&file)),
+        );
+
         let dropdown = DropDown::builder()
             .model(&model)
             .factory(&factory)
-            // TODO searchable ?
-            // .enable_search()
-            // .expression()
+            .enable_search(true)
+            .expression(&expression)
             .margin_bottom(4)
             .margin_top(4)
             .margin_start(4)
             .margin_end(4)
             .build();
 
-        dropdown.connect_activate(clone!(@weak model => move |_dropdown| {
-            let ron_path: PathBuf = [NAME, THEME_DIR].iter().collect();
-            // TODO more error handling
-            if let Some(mut themes) = xdg::BaseDirectories::with_prefix(ron_path).ok().map(|ron_dirs| ron_dirs.list_data_files(PathBuf::new())) {
-                themes.sort_by(|a, b| {
-                    let name_a = a.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
-                    let name_b = b.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
-                    name_a.cmp(&name_b)
-                });
-                model.splice(0, model.n_items(), &themes.iter().map(File::for_path).collect_vec());
-            }
-        }));
-
-        let ron_path: PathBuf = [NAME, THEME_DIR].iter().collect();
-        // TODO more error handling
-        if let Some(mut themes) = xdg::BaseDirectories::with_prefix(ron_path)
-            .ok()
-            .map(|ron_dirs| ron_dirs.list_data_files(PathBuf::new()))
-        {
-            themes.sort_by(|a, b| {
-                let name_a = a
-                    .file_stem()
-                    .map(|s| s.to_string_lossy())
-                    .unwrap_or_default();
-                let name_b = b
-                    .file_stem()
-                    .map(|s| s.to_string_lossy())
-                    .unwrap_or_default();
-                name_a.cmp(&name_b)
-            });
-            model.splice(
-                0,
-                model.n_items(),
-                &themes.iter().map(File::for_path).collect_vec(),
-            );
-        }
-
         dropdown.connect_selected_notify(clone!(@weak self_ => move|dropdown| {
+            if self_.imp().syncing.get() || theme_model::is_refreshing() {
+                return;
+            }
             if let Some(selected_item) = dropdown.selected_item() {
                 let file = selected_item
                 .downcast::<File>()
@@ -121,42 +132,284 @@ impl ThemeDropdown {
             }
         }));
 
+        let tags = DropDown::builder()
+            .model(&StringList::new(&[&fl!("all-tags")]))
+            .tooltip_text(&fl!("filter-by-tag"))
+            .margin_bottom(4)
+            .margin_top(4)
+            .margin_start(4)
+            .margin_end(4)
+            .build();
+        tags.connect_selected_notify(clone!(@weak self_, @weak filter => move |tags| {
+            let tag = match tags.selected() {
+                0 | GTK_INVALID_LIST_POSITION => None,
+                _ => tags
+                    .selected_item()
+                    .and_then(|o| o.downcast::<StringObject>().ok())
+                    .map(|s| s.string().to_string()),
+            };
+            let imp = self_.imp();
+            if *imp.tag.borrow() == tag {
+                return;
+            }
+            imp.tag.replace(tag);
+            // the dropdown moves its selection if the selected theme is filtered out
+            let syncing = imp.syncing.replace(true);
+            filter.changed(FilterChange::Different);
+            imp.syncing.set(syncing);
+        }));
+
         self_.append(&dropdown);
+        self_.append(&tags);
 
-        if let (Some(watch), Ok(config)) = (watch, Config::load()) {
-            let selected = match (watch, config) {
-                (Watch::Light, Config::DarkLight { light, .. }) => light,
-                (Watch::Dark, Config::DarkLight { dark, .. }) => dark,
-                (Watch::Static, Config::Static { name, .. }) => name,
-                _ => return self_,
-            };
+        let imp = self_.imp();
+        imp.dropdown.set(dropdown).unwrap();
+        imp.tags.set(tags).unwrap();
+        imp.watch.set(watch);
 
-            if let Some(selected) = (0..model.n_items()).position(|i| {
-                if let Some(o) = model.item(i) {
-                    let file = o
-                        .downcast::<File>()
-                        .expect("The item has to be an `GString`.");
-
-                    // Set "label" to "number"
-                    let basename = file.basename().unwrap_or_default();
-                    let name = basename
-                        .file_stem()
-                        .map(|s| s.to_string_lossy())
-                        .unwrap_or_default();
-                    name == selected
-                } else {
-                    false
-                }
-            }) {
-                dropdown.set_selected(selected.try_into().unwrap());
-            } else {
-                dropdown.set_selected(GTK_INVALID_LIST_POSITION);
+        let handler = themes.connect_items_changed(clone!(@weak self_ => move |_, _, _, _| {
+            self_.update_tags();
+        }));
+        imp.handlers.borrow_mut().push((themes.upcast(), handler));
+        self_.update_tags();
+
+        if watch.is_some() {
+            let mut handlers = imp.handlers.borrow_mut();
+            // the selected theme may be added or removed after the dropdown is created
+            let handler = model.connect_items_changed(clone!(@weak self_ => move |_, _, _, _| {
+                self_.sync_selection(Config::load().ok().as_ref());
+            }));
+            handlers.push((model.clone().upcast(), handler));
+            if let Some((monitor, handler)) =
+                theme_model::connect_config_changed(clone!(@weak self_ => move |config| {
+                    self_.sync_selection(Some(&config));
+                }))
+            {
+                handlers.push((monitor.upcast(), handler));
             }
-            // TODO watch config for changes
+            drop(handlers);
+            self_.sync_selection(Config::load().ok().as_ref());
         } else {
-            dropdown.set_selected(GTK_INVALID_LIST_POSITION);
+            imp.dropdown
+                .get()
+                .unwrap()
+                .set_selected(GTK_INVALID_LIST_POSITION);
         }
 
         self_
     }
+
+    /// a row with a swatch strip, the theme name, its source and a light / dark indicator
+    fn row() -> Box {
+        view! {
+            row = Box {
+                set_orientation: Orientation::Horizontal,
+                set_spacing: 8,
+
+                append: swatches = &DrawingArea {
+                    set_content_width: SWATCH_KEYS.len() as i32 * SWATCH_SIZE,
+                    set_content_height: SWATCH_SIZE,
+                    set_valign: Align::Center,
+                },
+                append: name = &Label {
+                    set_xalign: 0.0,
+                    set_hexpand: true,
+                },
+                append: source = &Label {
+                    add_css_class: "dim-label",
+                },
+                append: variant = &Image {},
+            }
+        };
+        row
+    }
+
+    fn bind_row(row: &Box, file: &File, watch: Option<Watch>) {
+        let swatches = row
+            .first_child()
+            .and_then(|c| c.downcast::<DrawingArea>().ok())
+            .expect("The first child has to be a `DrawingArea`.");
+        let name = swatches
+            .next_sibling()
+            .and_then(|c| c.downcast::<Label>().ok())
+            .expect("The second child has to be a `Label`.");
+        let source = name
+            .next_sibling()
+            .and_then(|c| c.downcast::<Label>().ok())
+            .expect("The third child has to be a `Label`.");
+        let variant = source
+            .next_sibling()
+            .and_then(|c| c.downcast::<Image>().ok())
+            .expect("The fourth child has to be an `Image`.");
+
+        name.set_label(&theme_name(file));
+
+        // user themes are not labelled
+        let (label, tooltip) = match theme_model::source(file).map(|(_, origin)| origin) {
+            Some(Origin::Extra { dir, writable }) => {
+                let label = dir
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let tooltip = if writable {
+                    dir.display().to_string()
+                } else {
+                    format!("{} ({})", dir.display(), fl!("read-only-theme-dir"))
+                };
+                (label, Some(tooltip))
+            }
+            Some(Origin::System) => (fl!("system-theme-source"), None),
+            Some(Origin::User) | None => (String::new(), None),
+        };
+        source.set_label(&label);
+        source.set_tooltip_text(tooltip.as_deref());
+        source.set_visible(!label.is_empty());
+
+        let theme = theme_model::load(file);
+        // show the variant that would be used for the watched slot
+        let overrides = theme.as_ref().and_then(|t| match watch {
+            Some(Watch::HighContrastLight) => t.resolve(false, true),
+            Some(Watch::HighContrastDark) => t.resolve(true, true),
+            _ => t.resolve(watch.and_then(Watch::variant) == Some(Variant::Dark), false),
+        });
+        let colors: Vec<Option<RGBA>> = SWATCH_KEYS
+            .iter()
+            .map(|key| {
+                overrides
+                    .as_ref()
+                    .and_then(|o| o.get_key(key))
+                    .and_then(|c| RGBA::parse(&c).ok())
+            })
+            .collect();
+
+        let paired = theme
+            .as_ref()
+            .map(|t| t.provides(Variant::Light) && t.provides(Variant::Dark))
+            .unwrap_or_default();
+        let (icon, tooltip) = if paired {
+            (
+                Some("weather-few-clouds-symbolic"),
+                Some(fl!("light-and-dark-theme")),
+            )
+        } else {
+            match overrides.as_ref().and_then(ColorOverrides::variant) {
+                Some(Variant::Dark) => (
+                    Some("weather-clear-night-symbolic"),
+                    Some(fl!("dark-theme")),
+                ),
+                Some(Variant::Light) => (Some("weather-clear-symbolic"), Some(fl!("light-theme"))),
+                None => (None, None),
+            }
+        };
+        variant.set_icon_name(icon);
+        variant.set_tooltip_text(tooltip.as_deref());
+
+        swatches.set_draw_func(move |_, cr, _, height| {
+            for (i, c) in colors.iter().enumerate() {
+                let x = (i as i32 * SWATCH_SIZE) as f64;
+                draw_swatch(cr, x, SWATCH_SIZE as f64, height as f64, *c);
+            }
+        });
+    }
+
+    /// sort `themes` by name, grouped by the dir they are loaded from,
+    /// with themes of the `preferred` variant first in each group
+    fn sorted(themes: &ListStore, preferred: Option<Variant>) -> SortListModel {
+        let sorter = CustomSorter::new(move |a, b| {
+            let key = |o: &glib::Object| {
+                let file = o
+                    .downcast_ref::<File>()
+                    .expect("The item has to be a `File`.");
+                let source = theme_model::source(file).map(|(i, _)| i);
+                let provided = match preferred {
+                    Some(preferred) => theme_model::load(file)
+                        .map(|t| t.provides(preferred))
+                        .unwrap_or_default(),
+                    None => true,
+                };
+                (source, !provided, theme_name(file))
+            };
+            key(a).cmp(&key(b)).into()
+        });
+        SortListModel::new(Some(themes), Some(&sorter))
+    }
+
+    /// list the tags of all themes in the tag filter, keeping the selected tag if it still exists
+    fn update_tags(&self) {
+        let imp = self.imp();
+        let tags = imp.tags.get().unwrap();
+        let list = tags
+            .model()
+            .and_then(|m| m.downcast::<StringList>().ok())
+            .expect("The tag model has to be a `StringList`.");
+
+        // the same tag may be spelled differently by different themes
+        let themes = theme_model::themes();
+        let mut found = BTreeMap::new();
+        for file in (0..themes.n_items()).filter_map(|i| themes.item(i)?.downcast::<File>().ok()) {
+            for tag in theme_model::load(&file)
+                .map(|t| t.metadata.tags)
+                .unwrap_or_default()
+            {
+                let tag = tag.trim().to_string();
+                if !tag.is_empty() {
+                    found.entry(tag.to_lowercase()).or_insert(tag);
+                }
+            }
+        }
+        let found: Vec<&str> = found.values().map(String::as_str).collect();
+
+        list.splice(1, list.n_items() - 1, &found);
+        let selected = imp
+            .tag
+            .borrow()
+            .as_ref()
+            .and_then(|tag| found.iter().position(|t| t.eq_ignore_ascii_case(tag)))
+            .map(|i| i as u32 + 1)
+            .unwrap_or(0);
+        tags.set_selected(selected);
+        tags.set_visible(!found.is_empty());
+    }
+
+    /// select the theme referenced by `config` for the watched slot, without emitting `theme-selected`
+    fn sync_selection(&self, config: Option<&Config>) {
+        let imp = self.imp();
+        let dropdown = imp.dropdown.get().unwrap();
+        let selected = match (imp.watch.get(), config.map(|c| &c.mode)) {
+            (Some(Watch::Light), Some(Mode::DarkLight { light, .. })) => light,
+            (Some(Watch::Dark), Some(Mode::DarkLight { dark, .. })) => dark,
+            (
+                Some(Watch::HighContrastLight),
+                Some(Mode::DarkLight {
+                    high_contrast_light,
+                    ..
+                }),
+            ) => high_contrast_light,
+            (
+                Some(Watch::HighContrastDark),
+                Some(Mode::DarkLight {
+                    high_contrast_dark, ..
+                }),
+            ) => high_contrast_dark,
+            (Some(Watch::Static), Some(Mode::Static { name })) => name,
+            _ => return,
+        };
+
+        let model = dropdown.model().expect("The dropdown has to have a model.");
+        let position = (0..model.n_items())
+            .find(|i| {
+                model
+                    .item(*i)
+                    .and_then(|o| o.downcast::<File>().ok())
+                    .map(|f| &theme_id(&f) == selected)
+                    .unwrap_or_default()
+            })
+            .unwrap_or(GTK_INVALID_LIST_POSITION);
+
+        if dropdown.selected() != position {
+            let syncing = imp.syncing.replace(true);
+            dropdown.set_selected(position);
+            imp.syncing.set(syncing);
+        }
+    }
 }
@@ -0,0 +1,48 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+use adw::ExpanderRow;
+use gtk4::{
+    glib::{self, subclass::Signal},
+    prelude::*,
+    subclass::prelude::*,
+    ListBox,
+};
+use once_cell::sync::{Lazy, OnceCell};
+use std::{cell::RefCell, rc::Rc};
+use user_colors::theme::Theme;
+
+// Object holding the state
+#[derive(Default)]
+pub struct ThemeHistory {
+    /// the theme open in the editor
+    pub document: OnceCell<Rc<RefCell<Theme>>>,
+    pub section: OnceCell<ExpanderRow>,
+    pub list: OnceCell<ListBox>,
+}
+
+// The central trait for subclassing a GObject
+#[glib::object_subclass]
+impl ObjectSubclass for ThemeHistory {
+    const NAME: &'static str = "ThemeHistoryWidget";
+    type Type = super::ThemeHistory;
+    type ParentType = gtk4::Box;
+}
+
+// Trait shared by all GObjects
+impl ObjectImpl for ThemeHistory {
+    fn signals() -> &'static [Signal] {
+        static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+            vec![Signal::builder("restore-revision")
+                .param_types(vec![u64::static_type()])
+                .return_type::<()>()
+                .build()]
+        });
+        SIGNALS.as_ref()
+    }
+}
+
+// Trait shared by all widgets
+impl WidgetImpl for ThemeHistory {}
+
+// Trait shared by all boxes
+impl BoxImpl for ThemeHistory {}
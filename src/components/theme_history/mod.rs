@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+use adw::{traits::ExpanderRowExt, ExpanderRow};
+use gtk4::{
+    gdk::RGBA, glib, prelude::*, subclass::prelude::*, Box, Button, Label, ListBox, Orientation,
+    SelectionMode,
+};
+use relm4_macros::view;
+use std::{cell::RefCell, rc::Rc};
+use user_colors::{
+    colors::KEYS,
+    history::{History, Revision},
+    theme::{Theme, VariantSlot},
+};
+
+use crate::{
+    fl,
+    util::{format_time, slot_name, swatch},
+};
+mod imp;
+
+const SWATCH_SIZE: i32 = 12;
+
+glib::wrapper! {
+    pub struct ThemeHistory(ObjectSubclass<imp::ThemeHistory>)
+        @extends gtk4::Box, gtk4::Widget,
+    @implements gtk4::Accessible, gtk4::Buildable, gtk4::ConstraintTarget, gtk4::Orientable;
+}
+
+/// a color which differs between a revision and the open theme
+struct Change {
+    slot: VariantSlot,
+    key: &'static str,
+    old: Option<String>,
+    new: Option<String>,
+}
+
+impl ThemeHistory {
+    /// a "Revision history" panel listing the saved versions of `document`
+    ///
+    /// Emits `restore-revision` with the time of the revision the user wants back.
+    pub fn new(document: Rc<RefCell<Theme>>) -> Self {
+        let self_: Self = glib::Object::new(&[]).expect("Failed to create Theme History");
+
+        let section = ExpanderRow::builder()
+            .title(&fl!("revision-history"))
+            .enable_expansion(true)
+            .expanded(false)
+            .hexpand(true)
+            .build();
+        let list = ListBox::builder()
+            .selection_mode(SelectionMode::None)
+            .build();
+        section.add_row(&list);
+        self_.append(&section);
+
+        let imp = self_.imp();
+        imp.document.set(document).unwrap();
+        imp.section.set(section).unwrap();
+        imp.list.set(list).unwrap();
+        self_.update();
+
+        self_
+    }
+
+    /// list the revisions of the document, e.g. after it was saved or a different theme was opened
+    pub fn update(&self) {
+        let imp = self.imp();
+        let list = imp.list.get().unwrap();
+        while let Some(row) = list.first_child() {
+            list.remove(&row);
+        }
+
+        let document = imp.document.get().unwrap().borrow().clone();
+        let revisions = History::new()
+            .and_then(|history| history.revisions(&document.id))
+            .unwrap_or_default();
+        imp.section
+            .get()
+            .unwrap()
+            .set_subtitle(&fl!("revision-count", count = revisions.len()));
+        for revision in &revisions {
+            list.append(&self.revision_row(&document, revision));
+        }
+    }
+
+    /// the time of `revision` with its changes compared to `current` and a restore button
+    fn revision_row(&self, current: &Theme, revision: &Revision) -> Box {
+        view! {
+            row = Box {
+                set_orientation: Orientation::Vertical,
+                set_spacing: 4,
+                set_margin_top: 4,
+                set_margin_bottom: 4,
+                set_margin_start: 4,
+                set_margin_end: 4,
+
+                append = &Box {
+                    set_orientation: Orientation::Horizontal,
+                    set_spacing: 4,
+                    append = &Label {
+                        set_text: &format_time(Some(revision.time / 1000)),
+                        set_hexpand: true,
+                        set_xalign: 0.0,
+                    },
+                    append: restore = &Button {
+                        set_label: &fl!("restore-revision"),
+                    },
+                },
+            }
+        };
+
+        let changes = revision
+            .load()
+            .map(|old| Self::changes(&old, current))
+            .unwrap_or_default();
+        if changes.is_empty() {
+            view! {
+                unchanged = Label {
+                    set_text: &fl!("revision-unchanged"),
+                    set_xalign: 0.0,
+                    add_css_class: "dim-label",
+                }
+            };
+            row.append(&unchanged);
+        }
+        for change in &changes {
+            row.append(&Self::change_row(change));
+        }
+
+        let time = revision.time;
+        restore.connect_clicked(glib::clone!(@weak self as self_ => move |_| {
+            self_.emit_by_name::<()>("restore-revision", &[&time]);
+        }));
+        row
+    }
+
+    /// the colors which differ between `old` and `new`, in all variants
+    fn changes(old: &Theme, new: &Theme) -> Vec<Change> {
+        VariantSlot::ALL
+            .iter()
+            .flat_map(|slot| KEYS.iter().map(move |key| (*slot, *key)))
+            .filter_map(|(slot, key)| {
+                let old = old.get(slot).and_then(|o| o.get_key(key));
+                let new = new.get(slot).and_then(|o| o.get_key(key));
+                (old != new).then_some(Change {
+                    slot,
+                    key,
+                    old,
+                    new,
+                })
+            })
+            .collect()
+    }
+
+    fn change_row(change: &Change) -> Box {
+        let slot = slot_name(change.slot);
+        let color = |c: &Option<String>| c.clone().unwrap_or_else(|| fl!("unset-color"));
+        let parse = |c: &Option<String>| c.as_deref().and_then(|c| RGBA::parse(c).ok());
+        let old_swatch = swatch(parse(&change.old), SWATCH_SIZE);
+        let new_swatch = swatch(parse(&change.new), SWATCH_SIZE);
+        view! {
+            row = Box {
+                set_orientation: Orientation::Horizontal,
+                set_spacing: 4,
+
+                append = &Label {
+                    set_text: &format!("{slot}: {}", change.key),
+                    set_hexpand: true,
+                    set_xalign: 0.0,
+                },
+                append: &old_swatch,
+                append = &Label {
+                    set_text: &color(&change.old),
+                    set_selectable: true,
+                },
+                append = &Label {
+                    set_text: "→",
+                },
+                append: &new_swatch,
+                append = &Label {
+                    set_text: &color(&change.new),
+                    set_selectable: true,
+                },
+            }
+        };
+        row
+    }
+}
@@ -2,10 +2,19 @@
 
 mod imp;
 
-use cascade::cascade;
-use gtk4::{glib, prelude::*, subclass::prelude::*, Button, FileChooserNative, Window};
 use std::path::PathBuf;
-use user_colors::{NAME, THEME_DIR};
+
+use cascade::cascade;
+use gtk4::{
+    glib, prelude::*, subclass::prelude::*, Button, FileChooserNative, MessageDialog, Window,
+};
+use user_colors::{
+    config::Config,
+    lint::{lint_file, Severity},
+    theme::Theme,
+};
+
+use crate::{fl, util::confirm_diagnostics};
 
 glib::wrapper! {
     pub struct ThemeImportButton(ObjectSubclass<imp::ThemeImportButton>)
@@ -36,7 +45,7 @@ impl ThemeImportButton {
                     .unwrap_or_default();
 
                 let file_chooser = FileChooserNative::new(
-                    Some("Import Theme"),
+                    Some(&fl!("import-theme-title")),
                     window.as_ref(),
                     gtk4::FileChooserAction::Open,
                     None,
@@ -47,16 +56,9 @@ impl ThemeImportButton {
                 file_chooser.connect_response(
                     glib::clone!(@weak self_ => move |file_chooser, response| {
                         if response != gtk4::ResponseType::Accept {return};
-                        if let Some(f) = file_chooser.file() {
-                            let ron_path: PathBuf = [NAME, THEME_DIR].iter().collect();
-                            let copy_err = xdg::BaseDirectories::with_prefix(ron_path).ok().and_then(|ron_dirs| ron_dirs.place_data_file(f.basename()?).ok()).and_then(|dest| {
-                                let source = f.path()?;
-                                std::fs::copy(source, dest).ok()
-                            }).is_none();
-                            if copy_err {
-                                // TODO toast error
-                            }
-                            // TODO Toast success
+                        if let Some(source) = file_chooser.file().and_then(|f| f.path()) {
+                            let window = self_.root().and_then(|root| root.downcast::<Window>().ok());
+                            glib::MainContext::default().spawn_local(Self::import(window, source));
                         }
                     }),
                 );
@@ -72,8 +74,40 @@ impl ThemeImportButton {
         );
     }
 
+    /// copy the theme at `source` to the user themes, asking first if the linter found problems
+    async fn import(window: Option<Window>, source: PathBuf) {
+        let result = match lint_file(&source) {
+            Ok(diagnostics) => {
+                let confirmed = match &window {
+                    Some(window) => {
+                        confirm_diagnostics(window, &diagnostics, &fl!("import-anyway")).await
+                    }
+                    // nobody can be asked without a window, so themes with problems are refused
+                    None => diagnostics.iter().all(|d| d.severity <= Severity::Info),
+                };
+                if !confirmed {
+                    return;
+                }
+                // imported themes get a new id, so they never replace an existing theme
+                Theme::import(&source, &Config::load().unwrap_or_default()).map(|_| ())
+            }
+            Err(err) => Err(err),
+        };
+        // TODO Toast success
+        if let (Err(err), Some(window)) = (result, window) {
+            let msg_dialog = MessageDialog::builder()
+                .transient_for(&window)
+                .modal(true)
+                .buttons(gtk4::ButtonsType::Close)
+                .text(&fl!("import-failed", error = err.to_string()))
+                .build();
+            let _ = msg_dialog.run_future().await;
+            msg_dialog.close();
+        }
+    }
+
     pub fn new() -> Self {
-        let button = Button::with_label("Import theme");
+        let button = Button::with_label(&fl!("import-theme"));
 
         let self_: Self = glib::Object::new(&[]).expect("Failed to create `ThemeImportButton`.");
         cascade! {
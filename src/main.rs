@@ -3,10 +3,12 @@ mod application;
 mod config;
 mod components;
 mod localize;
+mod theme_model;
 mod util;
 mod window;
 
 use gtk4::{gio, glib};
+use user_colors::lint::Severity;
 
 use self::application::ExampleApplication;
 use localize::localize;
@@ -17,6 +19,21 @@ fn main() {
     // Initialize logger
     pretty_env_logger::init();
 
+    // remove all customizations without opening the editor, e.g. when a theme made apps unreadable
+    if std::env::args().skip(1).any(|arg| arg == "--reset") {
+        if let Err(err) = user_colors::config::Config::reset_all() {
+            eprintln!("Failed to remove all customizations: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // check theme files without opening the editor, e.g. in the CI of a theme repository
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(|arg| arg == "--lint").unwrap_or_default() {
+        std::process::exit(lint(&args[1..]));
+    }
+
     // Prepare i18n
     localize();
 
@@ -27,3 +44,30 @@ fn main() {
     let app = ExampleApplication::new();
     app.run();
 }
+
+/// print the problems of the theme files at `paths`, returns 1 if any theme is broken
+fn lint(paths: &[String]) -> i32 {
+    let mut code = 0;
+    for path in paths {
+        match user_colors::lint::lint_file(path) {
+            Ok(diagnostics) => {
+                for d in diagnostics {
+                    if d.severity == Severity::Error {
+                        code = 1;
+                    }
+                    match d.slot {
+                        Some(slot) => {
+                            println!("{path}: {:?} in {slot:?}: {}", d.severity, d.problem)
+                        }
+                        None => println!("{path}: {:?}: {}", d.severity, d.problem),
+                    }
+                }
+            }
+            Err(err) => {
+                eprintln!("{path}: {err}");
+                code = 1;
+            }
+        }
+    }
+    code
+}
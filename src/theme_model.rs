@@ -0,0 +1,290 @@
+// SPDX-License-Identifier: MPL-2.0-only
+
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use gtk4::{
+    gio::{self, File, FileMonitor, FileMonitorEvent, FileMonitorFlags, ListStore},
+    glib::{self, SignalHandlerId},
+    prelude::*,
+};
+use user_colors::{
+    config::{Config, CONFIG_NAME},
+    store::{Origin, ThemeStore, XdgThemeStore},
+    theme::Theme,
+    NAME,
+};
+
+struct ThemeModel {
+    store: ListStore,
+    config_monitor: Option<FileMonitor>,
+    // the watched theme dirs, the monitors are kept alive for as long as the model exists
+    theme_monitors: RefCell<(Vec<PathBuf>, Vec<FileMonitor>)>,
+}
+
+thread_local! {
+    static THEME_MODEL: ThemeModel = ThemeModel::new();
+    static REFRESHING: Cell<bool> = Cell::new(false);
+    static LOADED: RefCell<HashMap<PathBuf, Option<Theme>>> = Default::default();
+    static SOURCES: RefCell<Vec<(PathBuf, Origin)>> = Default::default();
+    static STAMPS: RefCell<HashMap<PathBuf, Stamp>> = Default::default();
+}
+
+/// modification time and size of a theme file, to notice themes changed in place
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// The list of theme files shared by every theme dropdown in the editor.
+///
+/// The model holds a `gio::File` for each theme, sorted by id, and is kept
+/// up to date with the theme directories in all XDG data dirs and the extra
+/// theme dirs of the config.
+pub fn themes() -> ListStore {
+    THEME_MODEL.with(|m| m.store.clone())
+}
+
+/// Call `f` with the reloaded config whenever the config file changes on disk,
+/// including changes made outside of the editor.
+pub fn connect_config_changed<F: Fn(Config) + 'static>(
+    f: F,
+) -> Option<(FileMonitor, SignalHandlerId)> {
+    let monitor = THEME_MODEL.with(|m| m.config_monitor.clone())?;
+    let handler = monitor.connect_changed(move |_, _, _, event| {
+        if matches!(
+            event,
+            FileMonitorEvent::ChangesDoneHint
+                | FileMonitorEvent::Created
+                | FileMonitorEvent::MovedIn
+                | FileMonitorEvent::Renamed
+        ) {
+            // ignore partially written configs
+            if let Ok(config) = Config::load() {
+                f(config);
+            }
+        }
+    });
+    Some((monitor, handler))
+}
+
+/// Whether the model is being updated after a change on disk.
+///
+/// Selection changes caused by the update are not user choices and should be ignored.
+pub fn is_refreshing() -> bool {
+    REFRESHING.with(|r| r.get())
+}
+
+/// Id of the theme stored in `file`, which is referenced by the config.
+pub fn theme_id(file: &File) -> String {
+    file.basename()
+        .and_then(|b| b.file_stem().map(|s| s.to_string_lossy().into_owned()))
+        .unwrap_or_default()
+}
+
+/// Display name of the theme stored in `file`, falling back to its id.
+pub fn theme_name(file: &File) -> String {
+    load(file)
+        .map(|t| t.name)
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| theme_id(file))
+}
+
+/// Position of the theme dir containing `file` in the lookup order, and its origin.
+pub fn source(file: &File) -> Option<(usize, Origin)> {
+    let dir = file.parent()?.path()?;
+    SOURCES.with(|sources| {
+        sources
+            .borrow()
+            .iter()
+            .enumerate()
+            .find(|(_, (source, _))| *source == dir)
+            .map(|(i, (_, origin))| (i, origin.clone()))
+    })
+}
+
+/// Load the theme stored in `file`, cached until the theme directories change.
+pub fn load(file: &File) -> Option<Theme> {
+    let path = file.path()?;
+    LOADED.with(|loaded| {
+        loaded
+            .borrow_mut()
+            .entry(path)
+            .or_insert_with_key(|path| Theme::load(path).ok())
+            .clone()
+    })
+}
+
+impl ThemeModel {
+    fn new() -> Self {
+        let theme_store = theme_store().ok();
+        if let Some(theme_store) = &theme_store {
+            let _ = theme_store.init();
+        }
+        let store = ListStore::new(File::static_type());
+        refresh(&store);
+
+        let theme_dirs = theme_store.map(|s| s.dirs()).unwrap_or_default();
+        let theme_monitors = watch_theme_dirs(&store, &theme_dirs);
+
+        let config_monitor = xdg::BaseDirectories::with_prefix(NAME)
+            .ok()
+            .map(|xdg_dirs| {
+                xdg_dirs
+                    .get_config_home()
+                    .join(format!("{CONFIG_NAME}.ron"))
+            })
+            .and_then(|path| {
+                File::for_path(path)
+                    .monitor_file(FileMonitorFlags::WATCH_MOVES, gio::Cancellable::NONE)
+                    .ok()
+            });
+        if let Some(config_monitor) = &config_monitor {
+            // the extra theme dirs are listed in the config
+            config_monitor.connect_changed(|_, _, _, event| {
+                if matches!(
+                    event,
+                    FileMonitorEvent::ChangesDoneHint
+                        | FileMonitorEvent::Created
+                        | FileMonitorEvent::MovedIn
+                        | FileMonitorEvent::Renamed
+                ) {
+                    THEME_MODEL.with(ThemeModel::update_theme_dirs);
+                }
+            });
+        }
+
+        Self {
+            store,
+            config_monitor,
+            theme_monitors: RefCell::new((theme_dirs, theme_monitors)),
+        }
+    }
+
+    /// watch the theme dirs again and rescan them if they changed
+    fn update_theme_dirs(&self) {
+        let theme_dirs = match theme_store() {
+            Ok(theme_store) => theme_store.dirs(),
+            Err(_) => return,
+        };
+        if self.theme_monitors.borrow().0 == theme_dirs {
+            return;
+        }
+        let theme_monitors = watch_theme_dirs(&self.store, &theme_dirs);
+        for monitor in &self.theme_monitors.replace((theme_dirs, theme_monitors)).1 {
+            monitor.cancel();
+        }
+        refresh(&self.store);
+    }
+}
+
+/// Monitor `dirs`, refreshing `store` whenever a theme is changed.
+fn watch_theme_dirs(store: &ListStore, dirs: &[PathBuf]) -> Vec<FileMonitor> {
+    let theme_monitors = dirs
+        .iter()
+        .filter_map(|dir| {
+            File::for_path(dir)
+                .monitor_directory(FileMonitorFlags::WATCH_MOVES, gio::Cancellable::NONE)
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    for monitor in &theme_monitors {
+        monitor.connect_changed(glib::clone!(@weak store => move |_, _, _, event| {
+            if matches!(
+                event,
+                FileMonitorEvent::ChangesDoneHint
+                    | FileMonitorEvent::Deleted
+                    | FileMonitorEvent::Created
+                    | FileMonitorEvent::MovedIn
+                    | FileMonitorEvent::MovedOut
+                    | FileMonitorEvent::Renamed
+            ) {
+                refresh(&store);
+            }
+        }));
+    }
+    theme_monitors
+}
+
+/// the theme store with the extra theme dirs of the saved config
+fn theme_store() -> user_colors::error::Result<XdgThemeStore> {
+    let config = Config::load().unwrap_or_default();
+    XdgThemeStore::with_extra_dirs(config.theme_dirs())
+}
+
+/// Rescan the theme directories and update `store` in place.
+///
+/// Only the items that were added, removed or changed are spliced, so the selection of
+/// dropdowns using the model is kept for themes that still exist. Themes changed in
+/// place keep their file, they are replaced with themselves to update their rows.
+fn refresh(store: &ListStore) {
+    // TODO more error handling
+    let theme_store = match theme_store() {
+        Ok(theme_store) => theme_store,
+        Err(_) => return,
+    };
+    SOURCES.with(|sources| sources.replace(theme_store.sources()));
+    // sorted by id, user themes take precedence over extra and system themes
+    let themes = theme_store
+        .list()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|id| theme_store.path(&id))
+        .map(|p| File::for_path(&p))
+        .collect::<Vec<_>>();
+
+    let stamps: HashMap<PathBuf, Stamp> = themes
+        .iter()
+        .filter_map(|f| f.path())
+        .map(|p| {
+            let stamp = stamp(&p);
+            (p, stamp)
+        })
+        .collect();
+    let previous = STAMPS.with(|s| s.replace(stamps.clone()));
+    let changed = |f: &File| {
+        f.path()
+            .map(|p| previous.get(&p) != stamps.get(&p))
+            .unwrap_or_default()
+    };
+
+    LOADED.with(|loaded| loaded.borrow_mut().clear());
+    REFRESHING.with(|r| r.set(true));
+    let mut i = 0;
+    let mut themes = themes.into_iter().peekable();
+    while i < store.n_items() || themes.peek().is_some() {
+        let current = store.item(i).and_then(|o| o.downcast::<File>().ok());
+        match (current, themes.peek()) {
+            (Some(current), Some(new)) => match theme_id(&current).cmp(&theme_id(new)) {
+                Ordering::Less => store.remove(i),
+                Ordering::Greater => {
+                    store.insert(i, new);
+                    themes.next();
+                    i += 1;
+                }
+                Ordering::Equal => {
+                    if !current.equal(new) || changed(new) {
+                        store.splice(i, 1, &[new.clone()]);
+                    }
+                    themes.next();
+                    i += 1;
+                }
+            },
+            (Some(_), None) => store.remove(i),
+            (None, Some(new)) => {
+                store.append(new);
+                themes.next();
+                i += 1;
+            }
+            (None, None) => break,
+        }
+    }
+    REFRESHING.with(|r| r.set(false));
+}
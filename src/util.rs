@@ -1,10 +1,16 @@
 // SPDX-License-Identifier: MPL-2.0-only
 
-use gtk4::gdk::RGBA;
+use gtk4::{cairo, gdk::RGBA, glib, prelude::*, Align, DrawingArea, MessageDialog, Window};
 use hex::encode;
 // use kmeans_colors::{get_kmeans_hamerly, Kmeans, Sort};
 use palette::{rgb::Srgba, Pixel};
 use serde::{Deserialize, Serialize};
+use user_colors::{
+    lint::{Diagnostic, Severity},
+    theme::VariantSlot,
+};
+
+use crate::fl;
 
 #[derive(Copy, Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct SRGBA(pub Srgba);
@@ -15,6 +21,102 @@ pub fn hex_from_rgba(rgba: RGBA) -> String {
     format!("#{hex}")
 }
 
+/// a square of `color` with sides of `size`, or an outline if it is unset
+pub fn swatch(color: Option<RGBA>, size: i32) -> DrawingArea {
+    let swatch = DrawingArea::builder()
+        .content_width(size)
+        .content_height(size)
+        .valign(Align::Center)
+        .build();
+    swatch.set_draw_func(move |_, cr, width, height| {
+        draw_swatch(cr, 0.0, width as f64, height as f64, color);
+    });
+    swatch
+}
+
+/// draw a swatch of `color` at `x`, or an outline if it is unset
+pub fn draw_swatch(cr: &cairo::Context, x: f64, width: f64, height: f64, color: Option<RGBA>) {
+    cr.rectangle(x, 0.0, width, height);
+    if let Some(c) = color {
+        cr.set_source_rgba(
+            c.red() as f64,
+            c.green() as f64,
+            c.blue() as f64,
+            c.alpha() as f64,
+        );
+        let _ = cr.fill();
+    } else {
+        cr.set_source_rgba(0.5, 0.5, 0.5, 0.5);
+        let _ = cr.stroke();
+    }
+}
+
+/// a local date and time for `secs` since the unix epoch, or an empty string
+pub fn format_time(secs: Option<u64>) -> String {
+    secs.and_then(|secs| glib::DateTime::from_unix_local(secs as i64).ok())
+        .and_then(|time| time.format("%c").ok())
+        .map(|time| time.to_string())
+        .unwrap_or_default()
+}
+
+/// the translated name of a variant
+pub fn slot_name(slot: VariantSlot) -> String {
+    match slot {
+        VariantSlot::Light => fl!("light-variant"),
+        VariantSlot::Dark => fl!("dark-variant"),
+        VariantSlot::HighContrastLight => fl!("high-contrast-light-variant"),
+        VariantSlot::HighContrastDark => fl!("high-contrast-dark-variant"),
+    }
+}
+
+/// one line for each diagnostic, with its severity and variant
+pub fn format_diagnostics(diagnostics: &[Diagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(|d| {
+            let severity = match d.severity {
+                Severity::Error => fl!("lint-error"),
+                Severity::Warning => fl!("lint-warning"),
+                Severity::Info => fl!("lint-info"),
+            };
+            match d.slot {
+                Some(slot) => format!("{severity} · {}: {}", slot_name(slot), d.problem),
+                None => format!("{severity}: {}", d.problem),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// show the problems found in a theme and ask whether to `accept` it anyway
+///
+/// Returns `true` without asking if there is nothing more severe than [`Severity::Info`].
+pub async fn confirm_diagnostics(
+    window: &Window,
+    diagnostics: &[Diagnostic],
+    accept: &str,
+) -> bool {
+    let diagnostics: Vec<Diagnostic> = diagnostics
+        .iter()
+        .filter(|d| d.severity > Severity::Info)
+        .cloned()
+        .collect();
+    if diagnostics.is_empty() {
+        return true;
+    }
+    let confirm = MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .buttons(gtk4::ButtonsType::Cancel)
+        .text(&fl!("theme-problems"))
+        .secondary_text(&format_diagnostics(&diagnostics))
+        .build();
+    confirm.add_button(accept, gtk4::ResponseType::Accept);
+    let response = confirm.run_future().await;
+    confirm.close();
+    response == gtk4::ResponseType::Accept
+}
+
 impl SRGBA {
     pub fn into_inner(self) -> Srgba {
         self.0